@@ -0,0 +1,182 @@
+// density
+
+use wasm_bindgen::prelude::*;
+use js_sys::Float32Array;
+use hypergraph::VertexIndex;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::projection::bbox::normalize_bbox;
+use crate::projection::web_mercator::{lat_y, lng_x};
+
+// Gaussian kernel truncated at three standard deviations, normalized to sum to 1
+fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let radius = (3.0 * sigma).ceil() as i64;
+    let mut kernel: Vec<f64> = (-radius..=radius)
+        .map(|i| (-(i * i) as f64 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let sum: f64 = kernel.iter().sum();
+    for value in kernel.iter_mut() {
+        *value /= sum;
+    }
+    kernel
+}
+
+// One blur pass along rows (`horizontal`) or columns of a `width` x `height` grid
+fn blur(grid: &[f64], width: usize, height: usize, kernel: &[f64], horizontal: bool) -> Vec<f64> {
+    let radius = (kernel.len() / 2) as i64;
+    let mut out = vec![0.0; grid.len()];
+    for row in 0..height {
+        for col in 0..width {
+            let value = grid[row * width + col];
+            if value == 0.0 {
+                continue;
+            }
+            for (k, weight) in kernel.iter().enumerate() {
+                let offset = k as i64 - radius;
+                let (c, r) = if horizontal { (col as i64 + offset, row as i64) } else { (col as i64, row as i64 + offset) };
+                if c >= 0 && r >= 0 && (c as usize) < width && (r as usize) < height {
+                    out[r as usize * width + c as usize] += value * weight;
+                }
+            }
+        }
+    }
+    out
+}
+
+impl GraphWrapper {
+    // Kernel density of the node vertices over a Web Mercator raster of the bbox, row-major from the
+    // north-west corner. Points are binned into the raster (plus a margin for the kernel) and blurred
+    // with a separable Gaussian, so the cost does not grow with bandwidth times the number of points.
+    pub fn density(&self, bbox: [f64; 4], width: usize, height: usize, bandwidth: f64, weight_key: Option<&str>, thr_ids: Option<&[i32]>) -> Vec<f32> {
+        let [min_lng, min_lat, max_lng, max_lat] = bbox;
+        if width == 0 || height == 0 {
+            return Vec::new();
+        }
+
+        // A bbox crossing the antimeridian comes back in two parts, the raster spans both
+        let parts = normalize_bbox(min_lng, min_lat, max_lng, max_lat);
+        let x0 = lng_x(parts[0][0]);
+        let span_x = parts.iter().map(|part| lng_x(part[2]) - lng_x(part[0])).sum::<f64>().max(f64::EPSILON);
+        let y0 = lat_y(max_lat);
+        let span_y = (lat_y(min_lat) - y0).max(f64::EPSILON);
+
+        // A kernel wider than the raster only flattens it, and its margin would dwarf the raster
+        let sigma = bandwidth.clamp(0.1, width.max(height) as f64);
+        let kernel = gaussian_kernel(sigma);
+        let margin = kernel.len() / 2;
+        let (grid_width, grid_height) = (width + 2 * margin, height + 2 * margin);
+        let mut grid = vec![0.0; grid_width * grid_height];
+
+        for vertex in self.load_place_vertices() {
+            let node = match self.graph.get_vertex_weight(VertexIndex(vertex)) {
+                Ok(node) => node,
+                Err(_) => continue,
+            };
+            if let Some(thr_ids) = thr_ids {
+                if !node.thr_id.is_some_and(|thr_id| thr_ids.contains(&thr_id)) {
+                    continue;
+                }
+            }
+            let weight = match weight_key {
+                Some(key) => self.vertex_property(vertex, key).unwrap_or(0.0),
+                None => 1.0,
+            };
+            if weight == 0.0 {
+                continue;
+            }
+
+            // Offset from the bbox center taken the short way around the world
+            let dx = (lng_x(node.coords.lon) - x0 - span_x / 2.0 + 0.5).rem_euclid(1.0) - 0.5 + span_x / 2.0;
+            let col = (dx / span_x * width as f64).floor() + margin as f64;
+            let row = ((lat_y(node.coords.lat) - y0) / span_y * height as f64).floor() + margin as f64;
+            if col < 0.0 || row < 0.0 || col >= grid_width as f64 || row >= grid_height as f64 {
+                continue;
+            }
+            grid[row as usize * grid_width + col as usize] += weight;
+        }
+
+        let grid = blur(&grid, grid_width, grid_height, &kernel, true);
+        let grid = blur(&grid, grid_width, grid_height, &kernel, false);
+
+        let mut density = Vec::with_capacity(width * height);
+        for row in margin..margin + height {
+            for col in margin..margin + width {
+                density.push(grid[row * grid_width + col] as f32);
+            }
+        }
+        density
+    }
+}
+
+#[wasm_bindgen]
+impl GraphWrapper {
+    // `bandwidth` is the kernel's standard deviation in raster pixels, at most the raster's larger side.
+    // Cells hold the summed weights (1 per vertex, or the `weight_key` property) spread by the kernel,
+    // ready for a `width` x `height` single-channel float texture. `thr_ids` keeps only those thresholds.
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn density_grid(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, width: u32, height: u32, bandwidth: f64, weight_key: Option<String>, thr_ids: Option<Vec<i32>>) -> Float32Array {
+        let density = self.density(
+            [min_lng, min_lat, max_lng, max_lat],
+            width as usize,
+            height as usize,
+            bandwidth,
+            weight_key.as_deref(),
+            thr_ids.as_deref(),
+        );
+        Float32Array::from(&density[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn density_graph(points: &[(f64, f64, Option<i32>)]) -> GraphWrapper {
+        use crate::graph::suzaku_graph::{Coords, Node};
+
+        let mut graph = GraphWrapper::new();
+        for (id, (lon, lat, thr_id)) in points.iter().enumerate() {
+            graph.graph.add_vertex(Node::new(id, Coords { lon: *lon, lat: *lat }, *thr_id, true)).unwrap();
+        }
+        graph
+    }
+
+    #[test]
+    fn gaussian_kernel_sums_to_one() {
+        let kernel = gaussian_kernel(2.0);
+        assert_eq!(kernel.len(), 13);
+        assert!((kernel.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!(kernel[6] > kernel[5] && kernel[5] == kernel[7]);
+    }
+
+    #[test]
+    fn density_keeps_the_weight_of_points_inside() {
+        let graph = density_graph(&[(0.0, 0.0, Some(1)), (1.0, 1.0, Some(2)), (-1.0, 0.5, Some(2)), (50.0, 0.0, Some(1))]);
+        let density = graph.density([-5.0, -5.0, 5.0, 5.0], 64, 64, 2.0, None, None);
+        assert_eq!(density.len(), 64 * 64);
+        assert!((density.iter().map(|value| *value as f64).sum::<f64>() - 3.0).abs() < 1e-4);
+
+        let density = graph.density([-5.0, -5.0, 5.0, 5.0], 64, 64, 2.0, None, Some(&[2]));
+        assert!((density.iter().map(|value| *value as f64).sum::<f64>() - 2.0).abs() < 1e-4);
+
+        // The densest cell is the one holding the point at the center
+        let density = graph.density([-5.0, -5.0, 5.0, 5.0], 64, 64, 1.0, None, Some(&[1]));
+        let densest = (0..density.len()).max_by(|a, b| density[*a].total_cmp(&density[*b])).unwrap();
+        assert_eq!((densest % 64, densest / 64), (32, 32));
+    }
+
+    #[test]
+    fn density_spans_the_antimeridian_and_clamps_the_bandwidth() {
+        let graph = density_graph(&[(179.0, 0.0, None), (-179.0, 0.0, None)]);
+        let density = graph.density([170.0, -10.0, -170.0, 10.0], 40, 40, 1.0, None, None);
+        let total = density.iter().map(|value| *value as f64).sum::<f64>();
+        assert!((total - 2.0).abs() < 1e-4);
+        let west: f64 = (0..40).flat_map(|row| (0..20).map(move |col| row * 40 + col)).map(|index| density[index] as f64).sum();
+        assert!((west - 1.0).abs() < 1e-3);
+
+        // A bandwidth far beyond the raster is clamped to its size, so the margin stays small
+        let density = graph.density([170.0, -10.0, -170.0, 10.0], 8, 8, 1e6, None, None);
+        assert_eq!(density.len(), 64);
+        assert!(density.iter().all(|value| *value > 0.0));
+    }
+}
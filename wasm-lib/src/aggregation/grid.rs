@@ -0,0 +1,306 @@
+// grid
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_wasm_bindgen::{from_value, to_value};
+use hypergraph::VertexIndex;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::projection::bbox::normalize_bbox;
+use crate::projection::web_mercator::{from_pixel, to_pixel, world_size};
+use crate::supercluster::accumulators::{merge_states, statistics, AccumulatorDeclaration, AccumulatorState, StatisticValue};
+use crate::supercluster::filters::ClusterFilter;
+use crate::supercluster::supercluster::vertex_states;
+
+const SQRT_3: f64 = 1.7320508075688772;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CellShape {
+    Hexagon,
+    Square,
+}
+
+fn default_shape() -> CellShape {
+    CellShape::Hexagon
+}
+
+fn default_cell_size() -> f64 {
+    60.0
+}
+
+fn default_accumulators() -> Vec<AccumulatorDeclaration> {
+    vec![AccumulatorDeclaration::threshold_counter()]
+}
+
+// e.g. { shape: "square", cell_size: 40, filter: { thr_ids: [3] } }
+#[derive(Deserialize)]
+pub struct GridOptions {
+    #[serde(default = "default_shape")]
+    pub shape: CellShape,
+    // Screen distance between neighbouring cell centers in pixels, the same at every zoom
+    #[serde(default = "default_cell_size")]
+    pub cell_size: f64,
+    #[serde(default = "default_accumulators")]
+    pub accumulators: Vec<AccumulatorDeclaration>,
+    #[serde(default)]
+    pub filter: Option<ClusterFilter>,
+}
+
+impl Default for GridOptions {
+    fn default() -> Self {
+        Self { shape: default_shape(), cell_size: default_cell_size(), accumulators: default_accumulators(), filter: None }
+    }
+}
+
+struct CellBin {
+    count: usize,
+    states: Vec<AccumulatorState>,
+}
+
+// Bins of one zoom by cell
+type ZoomBins = Rc<HashMap<(i64, i64), CellBin>>;
+
+#[derive(Serialize)]
+pub struct GridCell {
+    // Cell column / row, axial (q, r) coordinates for hexagons
+    pub col: i64,
+    pub row: i64,
+    pub center: [f64; 2],
+    // Closed ring, lon / lat
+    pub polygon: Vec<[f64; 2]>,
+    pub count: usize,
+    pub statistics: HashMap<String, StatisticValue>,
+}
+
+// Bins vertices into uniform hexagonal or square cells laid out in screen pixels per zoom
+#[wasm_bindgen]
+pub struct GridAggregator {
+    shape: CellShape,
+    cell_size: f64,
+    accumulators: Vec<AccumulatorDeclaration>,
+    points: Vec<(f64, f64)>,
+    point_states: Vec<Vec<AccumulatorState>>,
+    // bins per zoom, filled on first query
+    zooms: RefCell<HashMap<usize, ZoomBins>>,
+}
+
+// Cube rounding of fractional axial coordinates
+fn round_axial(q: f64, r: f64) -> (i64, i64) {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    (rq as i64, rr as i64)
+}
+
+impl GridAggregator {
+    pub fn from_graph(graph: &GraphWrapper, options: GridOptions) -> Self {
+        let mut vertices = graph.load_place_vertices();
+        if let Some(filter) = &options.filter {
+            vertices = filter.select(graph, vertices);
+        }
+        // Positions and states from the same vertices, so they stay aligned
+        let (points, point_states) = vertices.iter()
+            .filter_map(|vertex| {
+                let node = graph.graph.get_vertex_weight(VertexIndex(*vertex)).ok()?;
+                Some(((node.coords.lon, node.coords.lat), vertex_states(&options.accumulators, graph, *vertex)))
+            })
+            .unzip();
+
+        Self {
+            shape: options.shape,
+            cell_size: options.cell_size.max(1.0),
+            accumulators: options.accumulators,
+            points,
+            point_states,
+            zooms: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Hexagon circumradius, pointy-top cells `cell_size` apart horizontally
+    fn hex_radius(&self) -> f64 {
+        self.cell_size / SQRT_3
+    }
+
+    fn cell_of(&self, x: f64, y: f64) -> (i64, i64) {
+        match self.shape {
+            CellShape::Square => ((x / self.cell_size).floor() as i64, (y / self.cell_size).floor() as i64),
+            CellShape::Hexagon => {
+                let radius = self.hex_radius();
+                round_axial((SQRT_3 / 3.0 * x - y / 3.0) / radius, (2.0 / 3.0 * y) / radius)
+            }
+        }
+    }
+
+    // Cell center in pixels
+    fn center_of(&self, cell: (i64, i64)) -> (f64, f64) {
+        let (col, row) = (cell.0 as f64, cell.1 as f64);
+        match self.shape {
+            CellShape::Square => ((col + 0.5) * self.cell_size, (row + 0.5) * self.cell_size),
+            CellShape::Hexagon => {
+                let radius = self.hex_radius();
+                (radius * SQRT_3 * (col + row / 2.0), radius * 1.5 * row)
+            }
+        }
+    }
+
+    fn corners(&self, cell: (i64, i64)) -> Vec<(f64, f64)> {
+        let (cx, cy) = self.center_of(cell);
+        match self.shape {
+            CellShape::Square => {
+                let (x0, y0) = (cx - self.cell_size / 2.0, cy - self.cell_size / 2.0);
+                let (x1, y1) = (x0 + self.cell_size, y0 + self.cell_size);
+                vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+            }
+            CellShape::Hexagon => {
+                let radius = self.hex_radius();
+                (0..6)
+                    .map(|i| {
+                        let angle = (60.0 * i as f64 - 30.0).to_radians();
+                        (cx + radius * angle.cos(), cy + radius * angle.sin())
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    fn bins(&self, zoom: usize) -> ZoomBins {
+        if let Some(bins) = self.zooms.borrow().get(&zoom) {
+            return bins.clone();
+        }
+
+        let mut bins: HashMap<(i64, i64), CellBin> = HashMap::new();
+        for (point, states) in self.points.iter().zip(&self.point_states) {
+            let (x, y) = to_pixel(point.0, point.1, zoom as f64);
+            let bin = bins.entry(self.cell_of(x, y)).or_insert_with(|| CellBin {
+                count: 0,
+                states: self.accumulators.iter().map(AccumulatorState::empty).collect(),
+            });
+            bin.count += 1;
+            merge_states(&mut bin.states, states);
+        }
+
+        let bins = Rc::new(bins);
+        self.zooms.borrow_mut().insert(zoom, bins.clone());
+        bins
+    }
+
+    // Cells whose center falls in the bbox, split at the antimeridian
+    pub fn cells(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> Vec<GridCell> {
+        let bins = self.bins(zoom);
+        let z = zoom as f64;
+        let ranges: Vec<((f64, f64), (f64, f64))> = normalize_bbox(min_lng, min_lat, max_lng, max_lat)
+            .into_iter()
+            .map(|b| (to_pixel(b[0], b[3], z), to_pixel(b[2], b[1], z)))
+            .collect();
+        let size = world_size(z);
+
+        let mut keys: Vec<&(i64, i64)> = bins.keys()
+            .filter(|cell| {
+                let (cx, cy) = self.center_of(**cell);
+                ranges.iter().any(|(min, max)| cx >= min.0 && cx <= max.0 && cy >= min.1 && cy <= max.1)
+            })
+            .collect();
+        keys.sort();
+
+        keys.into_iter()
+            .map(|cell| {
+                let bin = &bins[cell];
+                let (cx, cy) = self.center_of(*cell);
+                let (lng, lat) = from_pixel(cx, cy, z);
+                let mut polygon: Vec<[f64; 2]> = self.corners(*cell)
+                    .into_iter()
+                    .map(|(x, y)| {
+                        let (lng, lat) = from_pixel(x, y.clamp(0.0, size), z);
+                        [lng, lat]
+                    })
+                    .collect();
+                polygon.push(polygon[0]);
+
+                GridCell {
+                    col: cell.0,
+                    row: cell.1,
+                    center: [lng, lat],
+                    polygon,
+                    count: bin.count,
+                    statistics: statistics(&self.accumulators, &bin.states),
+                }
+            })
+            .collect()
+    }
+}
+
+#[wasm_bindgen]
+impl GridAggregator {
+    #[wasm_bindgen(constructor)]
+    pub fn new(graph: &GraphWrapper, options: JsValue) -> Result<GridAggregator, JsValue> {
+        let options: GridOptions = if options.is_undefined() || options.is_null() {
+            GridOptions::default()
+        } else {
+            from_value(options).map_err(|e| JsValue::from_str(&format!("Invalid grid options: {:?}", e)))?
+        };
+
+        Ok(GridAggregator::from_graph(graph, options))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_cells(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> JsValue {
+        to_value(&self.cells(min_lng, min_lat, max_lng, max_lat, zoom)).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_axial_keeps_cube_coordinates_consistent() {
+        assert_eq!(round_axial(0.0, 0.0), (0, 0));
+        assert_eq!(round_axial(0.2, 0.3), (0, 0));
+        assert_eq!(round_axial(0.4, 0.4), (0, 1));
+        assert_eq!(round_axial(0.6, 0.0), (1, 0));
+        assert_eq!(round_axial(-0.6, 0.1), (-1, 0));
+        // q and r both round up, but s = -q - r is closest: q is recomputed from r and s
+        assert_eq!(round_axial(0.55, 0.5), (1, 0));
+        assert_eq!(round_axial(2.1, -1.6), (2, -2));
+    }
+
+    #[test]
+    fn cell_of_finds_the_cell_around_its_center() {
+        for shape in [CellShape::Hexagon, CellShape::Square] {
+            let grid = GridAggregator::from_graph(&GraphWrapper::new(), GridOptions { shape, cell_size: 40.0, ..Default::default() });
+            for cell in [(0, 0), (3, -2), (-5, 7), (12, 4)] {
+                let (cx, cy) = grid.center_of(cell);
+                assert_eq!(grid.cell_of(cx, cy), cell);
+                // Every corner is shared with neighbours, points just inside it stay in the cell
+                for (x, y) in grid.corners(cell) {
+                    assert_eq!(grid.cell_of(cx + (x - cx) * 0.95, cy + (y - cy) * 0.95), cell);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bins_count_every_point_once() {
+        use crate::graph::suzaku_graph::{Coords, Node};
+
+        let mut graph = GraphWrapper::new();
+        for (id, (lon, lat)) in vec![(10.0, 10.0), (10.001, 10.001), (-40.0, 20.0), (170.0, -30.0)].into_iter().enumerate() {
+            graph.graph.add_vertex(Node::new(id, Coords { lon, lat }, Some(1), true)).unwrap();
+        }
+        let grid = GridAggregator::from_graph(&graph, GridOptions::default());
+        assert_eq!(grid.points.len(), grid.point_states.len());
+
+        let cells = grid.cells(-180.0, -85.0, 180.0, 85.0, 4);
+        assert_eq!(cells.iter().map(|cell| cell.count).sum::<usize>(), 4);
+        assert_eq!(cells.len(), 3);
+        assert!(cells.iter().all(|cell| cell.polygon.len() == 7 && cell.polygon[0] == cell.polygon[6]));
+    }
+}
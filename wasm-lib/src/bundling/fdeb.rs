@@ -0,0 +1,453 @@
+// force-directed edge bundling (Holten & van Wijk, 2009)
+
+use wasm_bindgen::prelude::*;
+use serde::Deserialize;
+use serde_wasm_bindgen::from_value;
+use js_sys::{Float64Array, Uint32Array};
+use hypergraph::VertexIndex;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::projection::web_mercator::{from_pixel, to_pixel};
+
+const EPSILON: f64 = 1e-9;
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct BundlingOptions {
+    // Spring constant K, higher keeps edges straighter
+    pub stiffness: f64,
+    // Edge pairs with compatibility below this never attract each other
+    pub compatibility_threshold: f64,
+    pub cycles: usize,
+    // Step size of the first cycle, as a fraction of the mean edge length
+    pub initial_step: f64,
+    pub initial_iterations: usize,
+    // Iteration count multiplier applied after each cycle
+    pub iterations_rate: f64,
+    pub initial_subdivisions: usize,
+    // Subdivision count multiplier applied after each cycle
+    pub subdivision_rate: usize,
+}
+
+impl Default for BundlingOptions {
+    fn default() -> Self {
+        Self {
+            stiffness: 0.1,
+            compatibility_threshold: 0.6,
+            cycles: 6,
+            initial_step: 0.002,
+            initial_iterations: 50,
+            iterations_rate: 2.0 / 3.0,
+            initial_subdivisions: 1,
+            subdivision_rate: 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Vec2 {
+    x: f64,
+    y: f64,
+}
+
+impl Vec2 {
+    fn sub(self, other: Vec2) -> Vec2 {
+        Vec2 { x: self.x - other.x, y: self.y - other.y }
+    }
+
+    fn add(self, other: Vec2) -> Vec2 {
+        Vec2 { x: self.x + other.x, y: self.y + other.y }
+    }
+
+    fn scale(self, k: f64) -> Vec2 {
+        Vec2 { x: self.x * k, y: self.y * k }
+    }
+
+    fn dot(self, other: Vec2) -> f64 {
+        self.x * other.x + self.y * other.y
+    }
+
+    fn length(self) -> f64 {
+        self.dot(self).sqrt()
+    }
+
+    fn lerp(self, other: Vec2, t: f64) -> Vec2 {
+        self.add(other.sub(self).scale(t))
+    }
+}
+
+struct BundledEdge {
+    relation_id: usize,
+    source: Vec2,
+    target: Vec2,
+    length: f64,
+    // Polyline including both endpoints
+    points: Vec<Vec2>,
+    // (other edge, whether it runs in the opposite direction)
+    compatible: Vec<(usize, bool)>,
+}
+
+fn project(lon: f64, lat: f64) -> Vec2 {
+    let (x, y) = to_pixel(lon, lat, 0.0);
+    Vec2 { x, y }
+}
+
+// Projection of `point` onto the infinite line through `a` and `b`
+fn project_on_line(point: Vec2, a: Vec2, b: Vec2) -> Vec2 {
+    let ab = b.sub(a);
+    let length_sq = ab.dot(ab);
+    if length_sq < EPSILON {
+        return a;
+    }
+    a.add(ab.scale(point.sub(a).dot(ab) / length_sq))
+}
+
+fn visibility(p: &BundledEdge, q: &BundledEdge) -> f64 {
+    let i0 = project_on_line(q.source, p.source, p.target);
+    let i1 = project_on_line(q.target, p.source, p.target);
+    let span = i1.sub(i0).length();
+    if span < EPSILON {
+        return 0.0;
+    }
+    let mid_i = i0.lerp(i1, 0.5);
+    let mid_p = p.source.lerp(p.target, 0.5);
+    (1.0 - 2.0 * mid_p.sub(mid_i).length() / span).max(0.0)
+}
+
+fn compatibility(p: &BundledEdge, q: &BundledEdge) -> f64 {
+    let vp = p.target.sub(p.source);
+    let vq = q.target.sub(q.source);
+    let l_avg = (p.length + q.length) / 2.0;
+
+    let angle = (vp.dot(vq) / (p.length * q.length)).abs();
+    let scale = 2.0 / (l_avg / p.length.min(q.length) + p.length.max(q.length) / l_avg);
+    let mid_p = p.source.lerp(p.target, 0.5);
+    let mid_q = q.source.lerp(q.target, 0.5);
+    let position = l_avg / (l_avg + mid_p.sub(mid_q).length());
+    let visibility = visibility(p, q).min(visibility(q, p));
+
+    angle * scale * position * visibility
+}
+
+// Resample a polyline into `segments` equal-length segments
+fn subdivide(points: &[Vec2], segments: usize) -> Vec<Vec2> {
+    let total: f64 = points.windows(2).map(|w| w[1].sub(w[0]).length()).sum();
+    let first = points[0];
+    let last = points[points.len() - 1];
+    if total < EPSILON || segments < 2 {
+        return vec![first, last];
+    }
+
+    let step = total / segments as f64;
+    let mut result = Vec::with_capacity(segments + 1);
+    result.push(first);
+
+    let mut walked = 0.0;
+    let mut target = step;
+    for window in points.windows(2) {
+        let length = window[1].sub(window[0]).length();
+        while result.len() < segments && walked + length >= target && length > EPSILON {
+            result.push(window[0].lerp(window[1], (target - walked) / length));
+            target += step;
+        }
+        walked += length;
+    }
+
+    while result.len() < segments {
+        result.push(last);
+    }
+    result.push(last);
+    result
+}
+
+// Bundles relation geometries between vertex coordinates. Run it in one go with `run`,
+// or a few iterations at a time with `step` to animate or keep frames responsive.
+#[wasm_bindgen]
+pub struct EdgeBundler {
+    options: BundlingOptions,
+    edges: Vec<BundledEdge>,
+    cycle: usize,
+    iteration: usize,
+    iterations: f64,
+    step_size: f64,
+    segments: usize,
+}
+
+impl EdgeBundler {
+    pub fn from_graph(graph: &GraphWrapper, options: BundlingOptions) -> Self {
+        let mut edges = Vec::new();
+
+        for (from, to, relation) in graph.load_relations() {
+            let (a, b) = match (graph.graph.get_vertex_weight(VertexIndex(from)), graph.graph.get_vertex_weight(VertexIndex(to))) {
+                (Ok(a), Ok(b)) => (a.coords, b.coords),
+                _ => continue,
+            };
+
+            // Take the short way across the antimeridian, the target longitude may leave [-180, 180]
+            let mut target_lon = b.lon;
+            if target_lon - a.lon > 180.0 {
+                target_lon -= 360.0;
+            } else if a.lon - target_lon > 180.0 {
+                target_lon += 360.0;
+            }
+
+            let source = project(a.lon, a.lat);
+            let target = project(target_lon, b.lat);
+            edges.push(BundledEdge {
+                relation_id: relation.id(),
+                source,
+                target,
+                length: target.sub(source).length(),
+                points: vec![source, target],
+                compatible: Vec::new(),
+            });
+        }
+
+        let mut bundler = Self {
+            iterations: options.initial_iterations as f64,
+            segments: options.initial_subdivisions.max(1) + 1,
+            step_size: 0.0,
+            cycle: 0,
+            iteration: 0,
+            options,
+            edges,
+        };
+        bundler.prepare();
+        bundler
+    }
+
+    fn prepare(&mut self) {
+        let bundled: Vec<usize> = (0..self.edges.len())
+            .filter(|&i| self.edges[i].length > EPSILON)
+            .collect();
+
+        for (n, &i) in bundled.iter().enumerate() {
+            for &j in &bundled[n + 1..] {
+                if compatibility(&self.edges[i], &self.edges[j]) >= self.options.compatibility_threshold {
+                    let p = &self.edges[i];
+                    let q = &self.edges[j];
+                    let reversed = p.target.sub(p.source).dot(q.target.sub(q.source)) < 0.0;
+                    self.edges[i].compatible.push((j, reversed));
+                    self.edges[j].compatible.push((i, reversed));
+                }
+            }
+        }
+
+        let mean_length = if bundled.is_empty() {
+            0.0
+        } else {
+            bundled.iter().map(|&i| self.edges[i].length).sum::<f64>() / bundled.len() as f64
+        };
+        self.step_size = self.options.initial_step * mean_length;
+
+        let segments = self.segments;
+        for edge in self.edges.iter_mut().filter(|edge| edge.length > EPSILON) {
+            edge.points = subdivide(&edge.points, segments);
+        }
+    }
+
+    fn iterate(&mut self) {
+        let mut moved: Vec<Vec<Vec2>> = Vec::with_capacity(self.edges.len());
+
+        for edge in &self.edges {
+            let count = edge.points.len();
+            if count <= 2 {
+                moved.push(edge.points.clone());
+                continue;
+            }
+
+            let spring = self.options.stiffness / (edge.length * (count - 1) as f64);
+            let mut points = edge.points.clone();
+
+            for i in 1..count - 1 {
+                let p = edge.points[i];
+                let spring_force = edge.points[i - 1].sub(p).add(edge.points[i + 1].sub(p)).scale(spring);
+
+                let mut electrostatic = Vec2 { x: 0.0, y: 0.0 };
+                for &(other, reversed) in &edge.compatible {
+                    let other_points = &self.edges[other].points;
+                    if other_points.len() != count {
+                        continue;
+                    }
+                    let q = if reversed { other_points[count - 1 - i] } else { other_points[i] };
+                    let diff = q.sub(p);
+                    let distance = diff.length();
+                    if distance > EPSILON {
+                        electrostatic = electrostatic.add(diff.scale(1.0 / distance));
+                    }
+                }
+
+                points[i] = p.add(spring_force.add(electrostatic).scale(self.step_size));
+            }
+
+            moved.push(points);
+        }
+
+        for (edge, points) in self.edges.iter_mut().zip(moved) {
+            edge.points = points;
+        }
+    }
+
+    fn next_cycle(&mut self) {
+        self.cycle += 1;
+        self.iteration = 0;
+        if self.is_done() {
+            return;
+        }
+
+        self.step_size /= 2.0;
+        self.iterations *= self.options.iterations_rate;
+        self.segments = (self.segments - 1) * self.options.subdivision_rate.max(1) + 1;
+
+        let segments = self.segments;
+        for edge in self.edges.iter_mut().filter(|edge| edge.length > EPSILON) {
+            edge.points = subdivide(&edge.points, segments);
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl EdgeBundler {
+    #[wasm_bindgen(constructor)]
+    pub fn new(graph: &GraphWrapper, options: JsValue) -> Result<EdgeBundler, JsValue> {
+        let options: BundlingOptions = if options.is_undefined() || options.is_null() {
+            BundlingOptions::default()
+        } else {
+            from_value(options).map_err(|e| JsValue::from_str(&format!("Invalid bundling options: {:?}", e)))?
+        };
+
+        Ok(EdgeBundler::from_graph(graph, options))
+    }
+
+    #[wasm_bindgen]
+    pub fn is_done(&self) -> bool {
+        self.cycle >= self.options.cycles
+    }
+
+    // Run up to `iterations` iterations, moving on to the next cycle when one finishes.
+    // Returns true once every cycle has completed.
+    #[wasm_bindgen]
+    pub fn step(&mut self, iterations: usize) -> bool {
+        for _ in 0..iterations {
+            if self.is_done() {
+                break;
+            }
+            if self.iteration as f64 >= self.iterations.round() {
+                self.next_cycle();
+                continue;
+            }
+            self.iterate();
+            self.iteration += 1;
+        }
+
+        self.is_done()
+    }
+
+    #[wasm_bindgen]
+    pub fn run(&mut self) {
+        while !self.step(usize::MAX) {}
+    }
+
+    // Relation id of each polyline, in output order
+    #[wasm_bindgen]
+    pub fn get_relation_ids(&self) -> Uint32Array {
+        let ids: Vec<u32> = self.edges.iter().map(|edge| edge.relation_id as u32).collect();
+        Uint32Array::from(&ids[..])
+    }
+
+    // Flat [lon, lat, lon, lat, ...] of every polyline, endpoints included
+    #[wasm_bindgen]
+    pub fn get_positions(&self) -> Float64Array {
+        let mut positions = Vec::new();
+        for edge in &self.edges {
+            for point in &edge.points {
+                let (lon, lat) = from_pixel(point.x, point.y, 0.0);
+                positions.push(lon);
+                positions.push(lat);
+            }
+        }
+        Float64Array::from(&positions[..])
+    }
+
+    // Start point of each polyline in `get_positions` (times two for the array index), plus the total at the end
+    #[wasm_bindgen]
+    pub fn get_offsets(&self) -> Uint32Array {
+        let mut offsets = Vec::with_capacity(self.edges.len() + 1);
+        let mut offset = 0u32;
+        offsets.push(offset);
+        for edge in &self.edges {
+            offset += edge.points.len() as u32;
+            offsets.push(offset);
+        }
+        Uint32Array::from(&offsets[..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_edge(source: (f64, f64), target: (f64, f64)) -> BundledEdge {
+        let (source, target) = (Vec2 { x: source.0, y: source.1 }, Vec2 { x: target.0, y: target.1 });
+        BundledEdge { relation_id: 0, source, target, length: target.sub(source).length(), points: vec![source, target], compatible: Vec::new() }
+    }
+
+    #[test]
+    fn subdivide_resamples_into_equal_segments() {
+        let line = [Vec2 { x: 0.0, y: 0.0 }, Vec2 { x: 3.0, y: 0.0 }, Vec2 { x: 3.0, y: 3.0 }];
+        let points = subdivide(&line, 3);
+        let xy: Vec<(f64, f64)> = points.iter().map(|point| (point.x, point.y)).collect();
+        assert_eq!(xy, vec![(0.0, 0.0), (2.0, 0.0), (3.0, 1.0), (3.0, 3.0)]);
+
+        // Degenerate polylines keep their endpoints only
+        assert_eq!(subdivide(&[Vec2 { x: 1.0, y: 1.0 }, Vec2 { x: 1.0, y: 1.0 }], 4).len(), 2);
+        assert_eq!(subdivide(&line, 1).len(), 2);
+    }
+
+    #[test]
+    fn compatibility_prefers_parallel_neighbours() {
+        let p = test_edge((0.0, 0.0), (10.0, 0.0));
+        let parallel = test_edge((0.0, 1.0), (10.0, 1.0));
+        let reversed = test_edge((10.0, 1.0), (0.0, 1.0));
+        let perpendicular = test_edge((5.0, -5.0), (5.0, 5.0));
+        let far = test_edge((100.0, 0.0), (110.0, 0.0));
+
+        assert!(compatibility(&p, &parallel) > 0.9);
+        assert_eq!(compatibility(&p, &parallel), compatibility(&p, &reversed));
+        assert!(compatibility(&p, &perpendicular) < 1e-9);
+        assert_eq!(compatibility(&p, &far), 0.0);
+    }
+
+    #[test]
+    fn bundling_pulls_parallel_relations_together() {
+        use crate::graph::suzaku_graph::{Coords, Node};
+
+        let mut graph = GraphWrapper::new();
+        for (lon, lat) in [(0.0, 0.0), (20.0, 0.0), (0.0, 2.0), (20.0, 2.0), (179.0, 50.0), (-179.0, 50.0)] {
+            graph.graph.add_vertex(Node::new(0, Coords { lon, lat }, None, true)).unwrap();
+        }
+        graph.create_relation(1, 0, 1, "fiber".to_string(), 1.0, None).unwrap();
+        graph.create_relation(2, 3, 2, "fiber".to_string(), 1.0, None).unwrap();
+        graph.create_relation(3, 4, 5, "fiber".to_string(), 1.0, None).unwrap();
+
+        let mut bundler = EdgeBundler::from_graph(&graph, BundlingOptions::default());
+        assert_eq!(bundler.edges[0].compatible, vec![(1, true)]);
+        // The antimeridian relation goes east to 181° instead of around the world
+        assert!((bundler.edges[2].length - project(181.0, 50.0).sub(project(179.0, 50.0)).length()).abs() < 1e-9);
+
+        let gap = |bundler: &EdgeBundler| {
+            let (a, b) = (&bundler.edges[0].points, &bundler.edges[1].points);
+            a[a.len() / 2].sub(b[b.len() / 2]).length()
+        };
+        let before = gap(&bundler);
+        assert!(!bundler.step(10));
+        bundler.run();
+        assert!(bundler.is_done());
+        assert!(gap(&bundler) < before);
+
+        // Endpoints never move
+        let edge = &bundler.edges[0];
+        assert_eq!((edge.points[0].x, edge.points[0].y), (edge.source.x, edge.source.y));
+        let last = edge.points[edge.points.len() - 1];
+        assert_eq!((last.x, last.y), (edge.target.x, edge.target.y));
+    }
+}
@@ -0,0 +1,305 @@
+// isochrone
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use serde_wasm_bindgen::to_value;
+use geo::{ConcaveHull, CoordsIter, HaversineDistance};
+use geo_types::{MultiPoint, Point, Polygon};
+use hypergraph::VertexIndex;
+use crate::graph::suzaku_graph::{Coords, GraphWrapper};
+use crate::projection::bbox::wrap_lng;
+
+#[derive(Serialize)]
+pub struct ReachedVertex {
+    pub vertex: usize,
+    pub cost: f64,
+}
+
+// A relation with only its `from` end within the budget, cut at `fraction` of its cost from `from`
+#[derive(Serialize)]
+pub struct PartialRelation {
+    pub relation_id: usize,
+    pub from: usize,
+    pub to: usize,
+    pub fraction: f64,
+    pub cut: Vec<f64>,
+}
+
+#[derive(Serialize)]
+pub struct IsochroneBand {
+    pub budget: f64,
+    pub vertices: Vec<usize>,
+    pub band_vertices: Vec<usize>,
+    pub partial_relations: Vec<PartialRelation>,
+    pub hull: Vec<Vec<f64>>,
+}
+
+#[derive(Serialize)]
+pub struct IsochroneResult {
+    pub source: usize,
+    pub reached: Vec<ReachedVertex>,
+    pub bands: Vec<IsochroneBand>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum CostKind {
+    Distance,
+    Weight,
+}
+
+impl CostKind {
+    pub fn parse(cost: &str) -> Result<CostKind, JsValue> {
+        match cost {
+            "distance" => Ok(CostKind::Distance),
+            "weight" => Ok(CostKind::Weight),
+            _ => Err(JsValue::from_str(&format!("Unknown cost '{}', expected 'distance' or 'weight'", cost))),
+        }
+    }
+}
+
+struct QueueEntry {
+    cost: f64,
+    vertex: usize,
+}
+
+impl PartialEq for QueueEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cost.to_bits() == other.cost.to_bits() && self.vertex == other.vertex
+    }
+}
+
+impl Eq for QueueEntry {}
+
+// Reversed so BinaryHeap pops the cheapest entry first
+impl Ord for QueueEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost).then_with(|| other.vertex.cmp(&self.vertex))
+    }
+}
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// (neighbour, relation id, cost)
+type Adjacency = Vec<Vec<(usize, usize, f64)>>;
+
+fn to_point(coords: Coords) -> Point<f64> {
+    Point::new(coords.lon, coords.lat)
+}
+
+impl GraphWrapper {
+    fn vertex_coords(&self, vertex: usize) -> Option<Coords> {
+        self.graph.get_vertex_weight(VertexIndex(vertex)).ok().map(|node| node.coords)
+    }
+
+    fn build_adjacency(&self, cost: CostKind, directed: bool) -> Adjacency {
+        let mut adjacency: Adjacency = vec![Vec::new(); self.graph.count_vertices()];
+
+        for (from, to, relation) in self.load_relations() {
+            let edge_cost = match cost {
+                CostKind::Weight => relation.weight,
+                CostKind::Distance => match (self.vertex_coords(from), self.vertex_coords(to)) {
+                    (Some(a), Some(b)) => to_point(a).haversine_distance(&to_point(b)),
+                    _ => continue,
+                },
+            };
+            // Dijkstra needs non-negative costs
+            let edge_cost = edge_cost.max(0.0);
+
+            if from < adjacency.len() && to < adjacency.len() {
+                adjacency[from].push((to, relation.id(), edge_cost));
+                if !directed {
+                    adjacency[to].push((from, relation.id(), edge_cost));
+                }
+            }
+        }
+
+        adjacency
+    }
+
+    // Cheapest cost from `source` to every vertex, stopping once `max_budget` is exceeded
+    fn shortest_costs(&self, adjacency: &Adjacency, source: usize, max_budget: f64) -> Vec<Option<f64>> {
+        let mut costs: Vec<Option<f64>> = vec![None; adjacency.len()];
+        let mut queue = BinaryHeap::new();
+
+        if source >= adjacency.len() {
+            return costs;
+        }
+        costs[source] = Some(0.0);
+        queue.push(QueueEntry { cost: 0.0, vertex: source });
+
+        while let Some(QueueEntry { cost, vertex }) = queue.pop() {
+            if costs[vertex].is_some_and(|best| cost > best) {
+                continue;
+            }
+            for &(next, _, edge_cost) in &adjacency[vertex] {
+                let next_cost = cost + edge_cost;
+                if next_cost > max_budget {
+                    continue;
+                }
+                if costs[next].is_none_or(|best| next_cost < best) {
+                    costs[next] = Some(next_cost);
+                    queue.push(QueueEntry { cost: next_cost, vertex: next });
+                }
+            }
+        }
+
+        costs
+    }
+
+    // Vertices reachable from `source` within each budget, as nested bands
+    pub fn isochrones(&self, source: usize, budgets: Vec<f64>, cost: CostKind, directed: bool, concavity: f64) -> IsochroneResult {
+        let mut budgets: Vec<f64> = budgets.into_iter().filter(|b| b.is_finite() && *b >= 0.0).collect();
+        budgets.sort_by(|a, b| a.total_cmp(b));
+        budgets.dedup();
+        let max_budget = budgets.last().copied().unwrap_or(0.0);
+
+        let adjacency = self.build_adjacency(cost, directed);
+        let costs = self.shortest_costs(&adjacency, source, max_budget);
+
+        let mut reached: Vec<ReachedVertex> = costs.iter().enumerate()
+            .filter_map(|(vertex, cost)| cost.map(|cost| ReachedVertex { vertex, cost }))
+            .collect();
+        reached.sort_by(|a, b| a.cost.total_cmp(&b.cost));
+
+        let mut previous = -1.0;
+        let mut bands = Vec::with_capacity(budgets.len());
+        for budget in budgets {
+            bands.push(self.isochrone_band(&adjacency, &costs, budget, previous, concavity));
+            previous = budget;
+        }
+
+        IsochroneResult { source, reached, bands }
+    }
+
+    fn isochrone_band(&self, adjacency: &Adjacency, costs: &[Option<f64>], budget: f64, previous: f64, concavity: f64) -> IsochroneBand {
+        let mut vertices = Vec::new();
+        let mut band_vertices = Vec::new();
+        let mut partial_relations = Vec::new();
+        // Undirected relations are in the adjacency of both ends
+        let mut cut_relations = HashSet::new();
+        let mut hull_points: Vec<Point<f64>> = Vec::new();
+
+        for (vertex, cost) in costs.iter().enumerate() {
+            let cost = match cost {
+                Some(cost) if *cost <= budget => *cost,
+                _ => continue,
+            };
+            vertices.push(vertex);
+            if cost > previous {
+                band_vertices.push(vertex);
+            }
+
+            let origin = match self.vertex_coords(vertex) {
+                Some(coords) => coords,
+                None => continue,
+            };
+            hull_points.push(to_point(origin));
+
+            // Relations leaving the reachable set are cut where the budget runs out
+            for &(next, relation_id, edge_cost) in &adjacency[vertex] {
+                if cost + edge_cost <= budget || edge_cost <= 0.0 {
+                    continue;
+                }
+                // Both ends within the budget, reached along another path
+                if costs[next].is_some_and(|next_cost| next_cost <= budget) {
+                    continue;
+                }
+                if !cut_relations.insert(relation_id) {
+                    continue;
+                }
+                let target = match self.vertex_coords(next) {
+                    Some(coords) => coords,
+                    None => continue,
+                };
+                let fraction = (budget - cost) / edge_cost;
+                // Short way around, relations across ±180° are cut on the antimeridian side
+                let mut delta_lon = target.lon - origin.lon;
+                if delta_lon > 180.0 {
+                    delta_lon -= 360.0;
+                } else if delta_lon < -180.0 {
+                    delta_lon += 360.0;
+                }
+                let cut = vec![
+                    wrap_lng(origin.lon + delta_lon * fraction),
+                    origin.lat + (target.lat - origin.lat) * fraction,
+                ];
+                hull_points.push(Point::new(cut[0], cut[1]));
+                partial_relations.push(PartialRelation { relation_id, from: vertex, to: next, fraction, cut });
+            }
+        }
+
+        let hull: Polygon<f64> = MultiPoint::from(hull_points).concave_hull(concavity);
+        let hull = hull.exterior().coords_iter()
+            .map(|coord| vec![coord.x, coord.y])
+            .collect();
+
+        IsochroneBand { budget, vertices, band_vertices, partial_relations, hull }
+    }
+}
+
+#[wasm_bindgen]
+impl GraphWrapper {
+    // Vertices reachable from `source` within each budget, as nested bands.
+    // `cost` is "distance" (haversine meters along relations) or "weight" (relation weights).
+    // `concavity` is passed to geo's concave hull, higher values give smoother outlines.
+    #[wasm_bindgen]
+    pub fn get_isochrones(&self, source: u32, budgets: Vec<f64>, cost: String, directed: bool, concavity: f64) -> Result<JsValue, JsValue> {
+        let cost = CostKind::parse(&cost)?;
+        let source = source as usize;
+
+        if self.graph.get_vertex_weight(VertexIndex(source)).is_err() {
+            return Err(JsValue::from_str(&format!("Unknown source vertex {}", source)));
+        }
+
+        Ok(to_value(&self.isochrones(source, budgets, cost, directed, concavity)).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isochrone_cuts_relations_leaving_the_budget() {
+        use crate::graph::suzaku_graph::Node;
+
+        // 0 - 1 - 2 in a line, 3 reached from both 1 and 2, 4 across the antimeridian from 0
+        let mut graph = GraphWrapper::new();
+        for (id, lon) in [(10, 170.0), (11, 171.0), (12, 172.0), (13, 171.5), (14, -170.0)] {
+            graph.graph.add_vertex(Node::new(id, Coords { lon, lat: 0.0 }, None, true)).unwrap();
+        }
+        graph.create_relation(1, 0, 1, "fiber".to_string(), 1.0, None).unwrap();
+        graph.create_relation(2, 1, 2, "fiber".to_string(), 1.0, None).unwrap();
+        graph.create_relation(3, 1, 3, "fiber".to_string(), 1.0, None).unwrap();
+        graph.create_relation(4, 2, 3, "fiber".to_string(), 4.0, None).unwrap();
+        graph.create_relation(5, 0, 4, "fiber".to_string(), 4.0, None).unwrap();
+
+        let result = graph.isochrones(0, vec![2.0, 1.0, -1.0], CostKind::Weight, false, 2.0);
+        assert_eq!(result.bands.len(), 2);
+        let costs: Vec<(usize, f64)> = result.reached.iter().map(|reached| (reached.vertex, reached.cost)).collect();
+        assert_eq!(costs, vec![(0, 0.0), (1, 1.0), (2, 2.0), (3, 2.0)]);
+
+        let band = &result.bands[0];
+        assert_eq!(band.vertices, vec![0, 1]);
+        let mut cut: Vec<usize> = band.partial_relations.iter().map(|partial| partial.relation_id).collect();
+        cut.sort_unstable();
+        assert_eq!(cut, vec![2, 3, 5]);
+
+        // Relation 4 has both ends within the budget, relation 5 (weight 4) is cut halfway with a budget of 2 left
+        let band = &result.bands[1];
+        assert_eq!(band.vertices, vec![0, 1, 2, 3]);
+        assert_eq!(band.band_vertices, vec![2, 3]);
+        assert_eq!(band.partial_relations.len(), 1);
+        let partial = &band.partial_relations[0];
+        assert_eq!((partial.relation_id, partial.from, partial.to), (5, 0, 4));
+        assert_eq!(partial.fraction, 0.5);
+        // Halfway from 170° to -170° the short way is ±180°, not 0°
+        assert_eq!(partial.cut, vec![-180.0, 0.0]);
+    }
+}
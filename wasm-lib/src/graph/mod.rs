@@ -1 +1,4 @@
 pub mod suzaku_graph;
+pub mod regions;
+pub mod isochrone;
+pub mod timeline;
@@ -0,0 +1,258 @@
+// regions
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use serde_wasm_bindgen::{from_value, to_value};
+use geo::{BoundingRect, Centroid, Contains};
+use geo_types::{Geometry, MultiPolygon, Point, Rect};
+use geojson::{Feature, GeoJson};
+use hypergraph::VertexIndex;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::projection::bbox::wrap_lng;
+
+struct Region {
+    name: String,
+    shape: MultiPolygon<f64>,
+    bounds: Rect<f64>,
+}
+
+#[derive(Serialize)]
+pub struct RegionNode {
+    pub index: usize,
+    pub name: String,
+    pub centroid: Vec<f64>,
+    pub vertex_count: usize,
+    pub internal_relations: usize,
+    pub thr_counts: HashMap<i32, usize>,
+    pub no_threshold: usize,
+}
+
+#[derive(Serialize)]
+pub struct RegionEdge {
+    pub source: usize,
+    pub target: usize,
+    pub count: usize,
+    pub weight: f64,
+}
+
+#[derive(Serialize)]
+pub struct RegionGraph {
+    // region index per vertex index, None when the vertex is outside every region
+    pub assignments: Vec<Option<usize>>,
+    pub regions: Vec<RegionNode>,
+    pub edges: Vec<RegionEdge>,
+    pub unassigned_vertices: usize,
+}
+
+// Region polygons loaded from GeoJSON, used to roll the graph up to regions
+#[wasm_bindgen]
+pub struct RegionIndex {
+    regions: Vec<Region>,
+}
+
+fn feature_name(feature: &Feature, name_property: &str, index: usize) -> String {
+    match feature.property(name_property) {
+        Some(serde_json::Value::String(name)) => name.clone(),
+        Some(serde_json::Value::Number(name)) => name.to_string(),
+        _ => match &feature.id {
+            Some(geojson::feature::Id::String(id)) => id.clone(),
+            Some(geojson::feature::Id::Number(id)) => id.to_string(),
+            None => index.to_string(),
+        },
+    }
+}
+
+fn feature_shape(feature: &Feature) -> Option<MultiPolygon<f64>> {
+    let geometry = feature.geometry.as_ref()?;
+    match Geometry::<f64>::try_from(&geometry.value) {
+        Ok(Geometry::Polygon(polygon)) => Some(MultiPolygon(vec![polygon])),
+        Ok(Geometry::MultiPolygon(multi_polygon)) => Some(multi_polygon),
+        _ => None,
+    }
+}
+
+impl RegionIndex {
+    // Features without a polygon geometry are skipped
+    pub fn from_features(features: &[Feature], name_property: &str) -> Self {
+        let mut regions = Vec::with_capacity(features.len());
+        for (index, feature) in features.iter().enumerate() {
+            if let Some(shape) = feature_shape(feature) {
+                if let Some(bounds) = shape.bounding_rect() {
+                    regions.push(Region { name: feature_name(feature, name_property, index), shape, bounds });
+                }
+            }
+        }
+
+        RegionIndex { regions }
+    }
+
+    // Index of the first region containing (lon, lat)
+    pub fn locate_point(&self, lon: f64, lat: f64) -> Option<usize> {
+        let point = Point::new(wrap_lng(lon), lat);
+
+        self.regions.iter().position(|region| {
+            let (min, max) = (region.bounds.min(), region.bounds.max());
+            point.x() >= min.x && point.x() <= max.x && point.y() >= min.y && point.y() <= max.y
+                && region.shape.contains(&point)
+        })
+    }
+
+    pub fn assign_vertices(&self, graph: &GraphWrapper) -> Vec<Option<usize>> {
+        let count = graph.graph.count_vertices();
+        let mut assignments = Vec::with_capacity(count);
+
+        for i in 0..count {
+            match graph.graph.get_vertex_weight(VertexIndex(i)) {
+                Ok(node) if node.is_node => assignments.push(self.locate_point(node.coords.lon, node.coords.lat)),
+                _ => assignments.push(None),
+            }
+        }
+
+        assignments
+    }
+
+    pub fn build_region_graph(&self, graph: &GraphWrapper) -> RegionGraph {
+        let assignments = self.assign_vertices(graph);
+
+        let mut regions: Vec<RegionNode> = self.regions.iter().enumerate()
+            .map(|(index, region)| RegionNode {
+                index,
+                name: region.name.clone(),
+                centroid: region.shape.centroid()
+                    .map(|c| vec![c.x(), c.y()])
+                    .unwrap_or_default(),
+                vertex_count: 0,
+                internal_relations: 0,
+                thr_counts: HashMap::new(),
+                no_threshold: 0,
+            })
+            .collect();
+
+        let mut unassigned_vertices = 0;
+        for (i, assignment) in assignments.iter().enumerate() {
+            let node = match graph.graph.get_vertex_weight(VertexIndex(i)) {
+                Ok(node) if node.is_node => node,
+                _ => continue,
+            };
+            match assignment {
+                Some(region) => {
+                    let region = &mut regions[*region];
+                    region.vertex_count += 1;
+                    match node.thr_id {
+                        Some(thr_id) => *region.thr_counts.entry(thr_id).or_insert(0) += 1,
+                        None => region.no_threshold += 1,
+                    }
+                }
+                None => unassigned_vertices += 1,
+            }
+        }
+
+        // Sum relations per unordered region pair
+        let mut edges: HashMap<(usize, usize), RegionEdge> = HashMap::new();
+        for (from, to, relation) in graph.load_relations() {
+            let (source, target) = match (assignments.get(from), assignments.get(to)) {
+                (Some(Some(source)), Some(Some(target))) => (*source, *target),
+                _ => continue,
+            };
+            if source == target {
+                regions[source].internal_relations += 1;
+                continue;
+            }
+            let key = (source.min(target), source.max(target));
+            let edge = edges.entry(key).or_insert(RegionEdge { source: key.0, target: key.1, count: 0, weight: 0.0 });
+            edge.count += 1;
+            edge.weight += relation.weight;
+        }
+
+        let mut edges: Vec<RegionEdge> = edges.into_values().collect();
+        edges.sort_by_key(|edge| (edge.source, edge.target));
+
+        RegionGraph { assignments, regions, edges, unassigned_vertices }
+    }
+}
+
+#[wasm_bindgen]
+impl RegionIndex {
+    // Accepts a GeoJSON FeatureCollection (or single Feature) of Polygon / MultiPolygon features.
+    // Regions are named by `name_property`, falling back to the feature id, then to the feature position.
+    #[wasm_bindgen(constructor)]
+    pub fn new(regions_geojson: JsValue, name_property: String) -> Result<RegionIndex, JsValue> {
+        let geojson: GeoJson = from_value(regions_geojson)
+            .map_err(|e| JsValue::from_str(&format!("Invalid GeoJSON: {:?}", e)))?;
+
+        let features = match geojson {
+            GeoJson::FeatureCollection(collection) => collection.features,
+            GeoJson::Feature(feature) => vec![feature],
+            GeoJson::Geometry(_) => return Err(JsValue::from_str("Expected a Feature or FeatureCollection")),
+        };
+
+        Ok(RegionIndex::from_features(&features, &name_property))
+    }
+
+    #[wasm_bindgen]
+    pub fn region_count(&self) -> usize {
+        self.regions.len()
+    }
+
+    #[wasm_bindgen]
+    pub fn locate(&self, lon: f64, lat: f64) -> Option<usize> {
+        self.locate_point(lon, lat)
+    }
+
+    #[wasm_bindgen]
+    pub fn get_region_graph(&self, graph: &GraphWrapper) -> JsValue {
+        to_value(&self.build_region_graph(graph)).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_graph_rolls_up_vertices_and_relations() {
+        use crate::graph::suzaku_graph::{Coords, Node};
+
+        let geojson: GeoJson = r#"{ "type": "FeatureCollection", "features": [
+            { "type": "Feature", "properties": { "name": "west" },
+              "geometry": { "type": "Polygon", "coordinates": [[[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]]] } },
+            { "type": "Feature", "id": "east", "properties": {},
+              "geometry": { "type": "Polygon", "coordinates": [[[10, 0], [20, 0], [20, 10], [10, 10], [10, 0]]] } },
+            { "type": "Feature", "properties": { "name": "no shape" }, "geometry": { "type": "Point", "coordinates": [0, 0] } }
+        ] }"#.parse().unwrap();
+        let features = match geojson {
+            GeoJson::FeatureCollection(collection) => collection.features,
+            _ => unreachable!(),
+        };
+        let index = RegionIndex::from_features(&features, "name");
+        assert_eq!(index.region_count(), 2);
+        assert_eq!(index.locate(5.0, 5.0), Some(0));
+        assert_eq!(index.locate(15.0, 5.0), Some(1));
+        assert_eq!(index.locate(15.0 + 360.0, 5.0), Some(1));
+        assert_eq!(index.locate(-5.0, 5.0), None);
+
+        let mut graph = GraphWrapper::new();
+        for (id, lon, thr_id) in [(1, 2.0, Some(1)), (2, 3.0, None), (3, 12.0, Some(2)), (4, 30.0, Some(2))] {
+            graph.graph.add_vertex(Node::new(id, Coords { lon, lat: 5.0 }, thr_id, true)).unwrap();
+        }
+        graph.create_relation(1, 0, 1, "fiber".to_string(), 1.0, None).unwrap();
+        graph.create_relation(2, 0, 2, "fiber".to_string(), 2.0, None).unwrap();
+        graph.create_relation(3, 2, 1, "fiber".to_string(), 3.0, None).unwrap();
+        graph.create_relation(4, 2, 3, "fiber".to_string(), 4.0, None).unwrap();
+
+        let region_graph = index.build_region_graph(&graph);
+        assert_eq!(region_graph.assignments, vec![Some(0), Some(0), Some(1), None]);
+        assert_eq!(region_graph.unassigned_vertices, 1);
+        assert_eq!(region_graph.regions[0].name, "west");
+        assert_eq!(region_graph.regions[1].name, "east");
+        assert_eq!(region_graph.regions[0].vertex_count, 2);
+        assert_eq!(region_graph.regions[0].no_threshold, 1);
+        assert_eq!(region_graph.regions[0].internal_relations, 1);
+        assert_eq!(region_graph.regions[1].thr_counts.get(&2), Some(&1));
+        assert_eq!(region_graph.edges.len(), 1);
+        assert_eq!((region_graph.edges[0].source, region_graph.edges[0].target), (0, 1));
+        assert_eq!((region_graph.edges[0].count, region_graph.edges[0].weight), (2, 5.0));
+    }
+}
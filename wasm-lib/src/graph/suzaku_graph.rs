@@ -1,19 +1,21 @@
 use std::collections::HashMap;
 use std::fmt;
 use wasm_bindgen::prelude::*;
-use serde_wasm_bindgen::to_value;
+use serde_wasm_bindgen::{from_value, to_value};
 use serde::{Serialize, Deserialize};
 
-use hypergraph::{Hypergraph, VertexIndex};
+use hypergraph::{HyperedgeIndex, Hypergraph, VertexIndex};
 use std::fmt::{Display, Formatter};
 
 use std::cmp::PartialEq;
 use std::hash::{Hash, Hasher};
 use hypergraph::errors::HypergraphError;
-use js_sys::{Array, Float64Array};
+use js_sys::{Array, Float64Array, Uint32Array};
 use crate::supercluster::SuperclusterWrapper;
+use crate::projection::web_mercator::pixel_distance_sq;
+use crate::projection::viewport::{fit_bbox, points_bbox};
+use crate::graph::timeline::TimelineInterval;
 use gloo_console::log;
-use hypergraph::iterator::HypergraphIterator;
 
 // Function to convert a Float64Array to Coords
 fn js_array_to_coords(array: &Float64Array) -> Coords {
@@ -22,14 +24,6 @@ fn js_array_to_coords(array: &Float64Array) -> Coords {
     Coords { lon, lat }
 }
 
-// Function to convert Coords to a Float64Array
-fn coords_to_js_array(coords: &Coords) -> Float64Array {
-    let array = Float64Array::new_with_length(2);
-    array.set_index(0, coords.lon);
-    array.set_index(1, coords.lat);
-    array
-}
-
 // Function to convert HypergraphError to JsValue
 fn convert_error_to_js_value(error: HypergraphError<Node, Relation>) -> JsValue {
     JsValue::from_str(&format!("Error adding vertex: {:?}", error))
@@ -66,8 +60,8 @@ impl Hash for Coords {
 #[wasm_bindgen]
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Node {
-    id: usize,
-    coords: Coords,
+    pub(crate) id: usize,
+    pub(crate) coords: Coords,
     pub thr_id: Option<i32>,  // Use Option<i32> to handle undefined values
     pub is_node: bool, // New field to indicate if it's a node or just a set of coordinates
 }
@@ -117,33 +111,63 @@ impl Node {
         self.id
     }
 
+    // Exported to JS, where Display is not reachable
+    #[allow(clippy::wrong_self_convention, clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         format!("Node {}", self.id)
     }
 }
 
 #[wasm_bindgen]
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Relation {
     id: usize,
+    pub weight: f64,
+    pub thr_id: Option<i32>,
 }
 
-impl Into<usize> for Relation {
-    fn into(self) -> usize {
-        self.id
+// Implement PartialEq for Relation
+impl PartialEq for Relation {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id && self.weight.to_bits() == other.weight.to_bits() && self.thr_id == other.thr_id
+    }
+}
+
+// Implement Eq for Relation
+impl Eq for Relation {}
+
+// Implement Hash for Relation
+impl Hash for Relation {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.weight.to_bits().hash(state);
+        self.thr_id.hash(state);
+    }
+}
+
+impl From<Relation> for usize {
+    fn from(relation: Relation) -> usize {
+        relation.id
     }
 }
 
 #[wasm_bindgen]
 impl Relation {
+    // Unit weight and no threshold
     pub fn new(id: usize) -> Self {
-        Self { id }
+        Self { id, weight: 1.0, thr_id: None }
+    }
+
+    pub fn with_weight(id: usize, weight: f64, thr_id: Option<i32>) -> Self {
+        Self { id, weight, thr_id }
     }
 
     pub fn id(&self) -> usize {
         self.id
     }
 
+    // Exported to JS, where Display is not reachable
+    #[allow(clippy::wrong_self_convention, clippy::inherent_to_string_shadow_display)]
     pub fn to_string(&self) -> String {
         format!("Relation {}", self.id)
     }
@@ -158,9 +182,16 @@ impl Display for Relation {
 
 #[wasm_bindgen]
 pub struct GraphWrapper {
-    graph: Hypergraph<Node, Relation>,
+    pub(crate) graph: Hypergraph<Node, Relation>,
+
+    // relation id -> (kind, hyperedge index)
+    pub(crate) relations: HashMap<usize, (String, usize)>,
+
+    // vertex index -> numeric properties used by cluster accumulators
+    pub(crate) properties: HashMap<usize, HashMap<String, f64>>,
 
-    relations: HashMap<usize, (String, usize)>
+    // vertex index -> validity intervals sorted by start, see `set_vertex_timeline`
+    pub(crate) timelines: HashMap<usize, Vec<TimelineInterval>>,
 }
 
 // Internal function to load vertex coordinates
@@ -192,34 +223,74 @@ impl GraphWrapper {
     }
 
 
-    pub fn load_threshold_ids(&self) -> Vec<usize> {
+    // Numeric property of a vertex. `thr_id` and `id` read the Node fields, other keys the vertex properties.
+    pub fn vertex_property(&self, vertex: usize, key: &str) -> Option<f64> {
+        match key {
+            "thr_id" => self.graph.get_vertex_weight(VertexIndex(vertex)).ok()?.thr_id.map(|thr_id| thr_id as f64),
+            "id" => self.graph.get_vertex_weight(VertexIndex(vertex)).ok().map(|node| node.id as f64),
+            _ => self.properties.get(&vertex)?.get(key).copied(),
+        }
+    }
 
+
+    // Vertex index of every place returned by `load_places`, in the same order
+    pub fn load_place_vertices(&self) -> Vec<usize> {
         let count = self.graph.count_vertices();
-        let mut thr_ids = Vec::with_capacity(count);
+        let mut vertices = Vec::with_capacity(count);
 
         for i in 0..count {
-            let vertex_index = VertexIndex(i);
-            match self.graph.get_vertex_weight(vertex_index) {
-                Ok(node) => {
-                    if node.is_node {
-                        if let Some(thr_id) = node.thr_id {
-                            // Only push thr_id if `is_node` is true and thr_id is Some
-                            thr_ids.push(thr_id as usize);
-                        }
-                    } else {
-
-                    }
-                },
-                Err(_) => {
-                    //coords.push((vec![])); // Handle vertex retrieval failure gracefully
+            if let Ok(node) = self.graph.get_vertex_weight(VertexIndex(i)) {
+                if node.is_node {
+                    vertices.push(i);
                 }
             }
         }
 
-        thr_ids
+        vertices
     }
 
 
+    // (source vertex index, target vertex index, relation) for every relation, ordered by relation id
+    pub fn load_relations(&self) -> Vec<(usize, usize, Relation)> {
+        let mut relations = Vec::with_capacity(self.relations.len());
+
+        for (_, hyperedge) in self.relations.values() {
+            let hyperedge_index = HyperedgeIndex(*hyperedge);
+            let vertices = match self.graph.get_hyperedge_vertices(hyperedge_index) {
+                Ok(vertices) => vertices,
+                Err(_) => continue,
+            };
+            if let (Some(from), Some(to), Ok(relation)) = (vertices.first(), vertices.last(), self.graph.get_hyperedge_weight(hyperedge_index)) {
+                relations.push((from.0, to.0, *relation));
+            }
+        }
+
+        relations.sort_by_key(|(_, _, relation)| relation.id);
+        relations
+    }
+
+
+    // Vertex indices within `tolerance_px` screen pixels of (lon, lat) at `zoom`, nearest first
+    pub fn vertices_within_pixels(&self, lon: f64, lat: f64, zoom: f64, tolerance_px: f64) -> Vec<(usize, f64)> {
+        let tolerance_sq = tolerance_px * tolerance_px;
+        let count = self.graph.count_vertices();
+        let mut hits = Vec::new();
+
+        for i in 0..count {
+            if let Ok(node) = self.graph.get_vertex_weight(VertexIndex(i)) {
+                if !node.is_node {
+                    continue;
+                }
+                let dist_sq = pixel_distance_sq((lon, lat), (node.coords.lon, node.coords.lat), zoom);
+                if dist_sq <= tolerance_sq {
+                    hits.push((i, dist_sq));
+                }
+            }
+        }
+
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+        hits
+    }
 
 }
 
@@ -233,6 +304,8 @@ impl GraphWrapper {
         GraphWrapper {
             graph,
             relations: HashMap::new(),
+            properties: HashMap::new(),
+            timelines: HashMap::new(),
         }
     }
 
@@ -242,6 +315,25 @@ impl GraphWrapper {
         SuperclusterWrapper::new(self, max_zoom, radius)
     }
 
+    #[wasm_bindgen]
+    pub fn create_supercluster_with_options(&self, max_zoom: usize, radius: f64, options: JsValue) -> Result<SuperclusterWrapper, JsValue> {
+        SuperclusterWrapper::with_options(self, max_zoom, radius, options)
+    }
+
+    // Replace the numeric properties of a vertex, e.g. { traffic: 120, customers: 4 }
+    #[wasm_bindgen]
+    pub fn set_vertex_properties(&mut self, vertex_index: u32, properties: JsValue) -> Result<(), JsValue> {
+        let properties: HashMap<String, f64> = from_value(properties)
+            .map_err(|e| JsValue::from_str(&format!("Invalid vertex properties: {:?}", e)))?;
+        self.properties.insert(vertex_index as usize, properties);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn set_vertex_property(&mut self, vertex_index: u32, key: String, value: f64) {
+        self.properties.entry(vertex_index as usize).or_default().insert(key, value);
+    }
+
     // Create a vertex
     #[wasm_bindgen]
     pub fn create_vertex(&mut self, id: usize, coords_array: Float64Array, thr_id: Option<i32>, is_node: bool) -> Result<u32, JsValue> {
@@ -259,6 +351,36 @@ impl GraphWrapper {
     }
 
 
+    // Create a relation between two vertices
+    #[wasm_bindgen]
+    pub fn create_relation(&mut self, id: usize, from: u32, to: u32, kind: String, weight: f64, thr_id: Option<i32>) -> Result<u32, JsValue> {
+        let relation = Relation::with_weight(id, weight, thr_id);
+
+        match self.graph.add_hyperedge(vec![VertexIndex(from as usize), VertexIndex(to as usize)], relation) {
+            Ok(hyperedge_index) => {
+                self.relations.insert(id, (kind, hyperedge_index.0));
+                Ok(hyperedge_index.0 as u32)
+            },
+            Err(e) => Err(JsValue::from_str(&format!("Error adding relation: {:?}", e))),
+        }
+    }
+
+
+    // Replace the coordinates and threshold of an existing vertex, keeping its id
+    #[wasm_bindgen]
+    pub fn update_vertex(&mut self, vertex_index: u32, coords_array: Float64Array, thr_id: Option<i32>, is_node: bool) -> Result<(), JsValue> {
+        let vertex_index = VertexIndex(vertex_index as usize);
+        let id = match self.graph.get_vertex_weight(vertex_index) {
+            Ok(node) => node.id,
+            Err(e) => return Err(JsValue::from_str(&format!("Error retrieving vertex: {:?}", e))),
+        };
+        let coords = js_array_to_coords(&coords_array);
+
+        self.graph.update_vertex_weight(vertex_index, Node { id, coords, thr_id, is_node })
+            .map_err(|e| JsValue::from_str(&format!("Error updating vertex: {:?}", e)))
+    }
+
+
     #[wasm_bindgen]
     pub fn get_vertex_weight(&self, vertex_index: u32) -> Result<JsValue, JsValue> {
         // Convert u32 to VertexIndex if needed
@@ -295,11 +417,52 @@ impl GraphWrapper {
 
 
 
+    // Hit-testing in screen pixels, using the same projection as the clustering
+    #[wasm_bindgen]
+    pub fn get_vertices_within_pixels(&self, lon: f64, lat: f64, zoom: f64, tolerance_px: f64) -> Uint32Array {
+        let indices: Vec<u32> = self.vertices_within_pixels(lon, lat, zoom, tolerance_px)
+            .into_iter()
+            .map(|(i, _)| i as u32)
+            .collect();
+
+        Uint32Array::from(&indices[..])
+    }
+
+    #[wasm_bindgen]
+    pub fn get_nearest_vertex(&self, lon: f64, lat: f64, zoom: f64, tolerance_px: f64) -> Option<u32> {
+        self.vertices_within_pixels(lon, lat, zoom, tolerance_px)
+            .first()
+            .map(|(i, _)| *i as u32)
+    }
+
+    // Center and zoom framing the vertices in a [width, height] viewport, e.g. fit_bounds(ids, [800, 600], 40).
+    // Returns { center: [lng, lat], zoom, bbox }.
+    #[wasm_bindgen]
+    pub fn fit_bounds(&self, vertex_ids: &[u32], viewport_px: &[f64], padding: f64) -> Result<JsValue, JsValue> {
+        let (width, height) = match viewport_px {
+            [width, height] => (*width, *height),
+            _ => return Err(JsValue::from_str("viewport_px has to be [width, height]")),
+        };
+        let points: Vec<(f64, f64)> = vertex_ids.iter()
+            .filter_map(|vertex| self.graph.get_vertex_weight(VertexIndex(*vertex as usize)).ok())
+            .map(|node| (node.coords.lon, node.coords.lat))
+            .collect();
+
+        match points_bbox(&points) {
+            Some(bbox) => Ok(to_value(&fit_bbox(bbox, width, height, padding)).unwrap()),
+            None => Err(JsValue::from_str("None of the vertices exist")),
+        }
+    }
+
+
     #[wasm_bindgen]
     pub fn graph_clear(&mut self) -> Result<(), JsValue> {
         // Clear the hypergraph
         // Example: Assuming self.graph has a method `clear` or similar
         self.graph.clear(); // Replace with the actual method or logic to clear your hypergraph
+        self.relations.clear();
+        self.properties.clear();
+        self.timelines.clear();
 
         // Return Ok() to indicate success
         Ok(())
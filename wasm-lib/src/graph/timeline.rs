@@ -0,0 +1,82 @@
+// timeline
+
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_wasm_bindgen::from_value;
+use crate::graph::suzaku_graph::GraphWrapper;
+
+// A span of time a vertex exists in, with the property values in effect during it.
+// Times are plain numbers, e.g. epoch milliseconds, as long as every timeline uses the same unit.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TimelineInterval {
+    pub start: f64,
+    // Open-ended when omitted
+    #[serde(default)]
+    pub end: Option<f64>,
+    // Overrides of the vertex properties during the interval, `thr_id` included
+    #[serde(default)]
+    pub properties: HashMap<String, f64>,
+}
+
+impl TimelineInterval {
+    pub fn contains(&self, time: f64) -> bool {
+        time >= self.start && self.end.is_none_or(|end| time < end)
+    }
+
+    pub fn overlaps(&self, start: f64, end: f64) -> bool {
+        self.start <= end && self.end.is_none_or(|interval_end| interval_end > start)
+    }
+}
+
+impl GraphWrapper {
+    // Property of a vertex while `interval` is in effect, the interval's override or the vertex property
+    pub fn interval_property(&self, vertex: usize, interval: &TimelineInterval, key: &str) -> Option<f64> {
+        interval.properties.get(key).copied().or_else(|| self.vertex_property(vertex, key))
+    }
+}
+
+#[wasm_bindgen]
+impl GraphWrapper {
+    // When a vertex exists and what its status is meanwhile, e.g.
+    // [{ start: 1700000000000, end: 1700003600000, properties: { thr_id: 2 } }, { start: 1700003600000 }]
+    // Where intervals overlap the later starting one is in effect. Vertices without a timeline always exist.
+    #[wasm_bindgen]
+    pub fn set_vertex_timeline(&mut self, vertex_index: u32, intervals: JsValue) -> Result<(), JsValue> {
+        let mut intervals: Vec<TimelineInterval> = from_value(intervals)
+            .map_err(|e| JsValue::from_str(&format!("Invalid vertex timeline: {:?}", e)))?;
+        intervals.sort_by(|a, b| a.start.total_cmp(&b.start));
+        self.timelines.insert(vertex_index as usize, intervals);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn clear_vertex_timeline(&mut self, vertex_index: u32) -> bool {
+        self.timelines.remove(&(vertex_index as usize)).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_are_half_open() {
+        let interval = TimelineInterval { start: 10.0, end: Some(20.0), properties: HashMap::new() };
+        assert!(!interval.contains(9.9));
+        assert!(interval.contains(10.0));
+        assert!(interval.contains(19.9));
+        assert!(!interval.contains(20.0));
+
+        assert!(interval.overlaps(0.0, 10.0));
+        assert!(interval.overlaps(15.0, 16.0));
+        assert!(interval.overlaps(19.0, 30.0));
+        assert!(!interval.overlaps(20.0, 30.0));
+        assert!(!interval.overlaps(0.0, 9.0));
+
+        let open = TimelineInterval { start: 10.0, end: None, properties: HashMap::new() };
+        assert!(open.contains(1e15));
+        assert!(open.overlaps(1e15, 2e15));
+        assert!(!open.overlaps(0.0, 5.0));
+    }
+}
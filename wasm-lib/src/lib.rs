@@ -1,5 +1,9 @@
 mod graph;
 mod supercluster;
+mod projection;
+mod bundling;
+mod mvt;
+mod aggregation;
 // mod utils;
 // mod algorithms;
 
@@ -0,0 +1,293 @@
+// encoder
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+// Mapbox Vector Tile 2.1 protobuf encoding, just the parts needed to write point and line layers
+
+const WIRE_VARINT: u64 = 0;
+const WIRE_FIXED64: u64 = 1;
+const WIRE_LENGTH: u64 = 2;
+
+const GEOM_POINT: u64 = 1;
+const GEOM_LINESTRING: u64 = 2;
+
+const CMD_MOVE_TO: u32 = 1;
+const CMD_LINE_TO: u32 = 2;
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn write_tag(buf: &mut Vec<u8>, field: u64, wire_type: u64) {
+    write_varint(buf, (field << 3) | wire_type);
+}
+
+fn write_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    write_tag(buf, field, WIRE_LENGTH);
+    write_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn write_packed(buf: &mut Vec<u8>, field: u64, values: &[u32]) {
+    let mut packed = Vec::with_capacity(values.len());
+    for value in values {
+        write_varint(&mut packed, *value as u64);
+    }
+    write_bytes(buf, field, &packed);
+}
+
+fn zigzag(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+fn command(id: u32, count: usize) -> u32 {
+    (id & 0x7) | ((count as u32) << 3)
+}
+
+// A feature attribute value
+#[derive(Clone, Debug)]
+pub enum TagValue {
+    String(String),
+    Double(f64),
+    Int(i64),
+    Uint(u64),
+    Bool(bool),
+}
+
+impl PartialEq for TagValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TagValue::String(a), TagValue::String(b)) => a == b,
+            (TagValue::Double(a), TagValue::Double(b)) => a.to_bits() == b.to_bits(),
+            (TagValue::Int(a), TagValue::Int(b)) => a == b,
+            (TagValue::Uint(a), TagValue::Uint(b)) => a == b,
+            (TagValue::Bool(a), TagValue::Bool(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for TagValue {}
+
+impl Hash for TagValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            TagValue::String(value) => (0u8, value).hash(state),
+            TagValue::Double(value) => (1u8, value.to_bits()).hash(state),
+            TagValue::Int(value) => (2u8, value).hash(state),
+            TagValue::Uint(value) => (3u8, value).hash(state),
+            TagValue::Bool(value) => (4u8, value).hash(state),
+        }
+    }
+}
+
+impl TagValue {
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        match self {
+            TagValue::String(value) => write_bytes(&mut buf, 1, value.as_bytes()),
+            TagValue::Double(value) => {
+                write_tag(&mut buf, 3, WIRE_FIXED64);
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+            TagValue::Int(value) => {
+                write_tag(&mut buf, 4, WIRE_VARINT);
+                write_varint(&mut buf, *value as u64);
+            }
+            TagValue::Uint(value) => {
+                write_tag(&mut buf, 5, WIRE_VARINT);
+                write_varint(&mut buf, *value);
+            }
+            TagValue::Bool(value) => {
+                write_tag(&mut buf, 7, WIRE_VARINT);
+                write_varint(&mut buf, *value as u64);
+            }
+        }
+        buf
+    }
+}
+
+// One layer of a tile. Keys and values are shared between features as the spec requires.
+pub struct LayerBuilder {
+    name: String,
+    extent: u32,
+    keys: Vec<String>,
+    key_index: HashMap<String, u32>,
+    values: Vec<TagValue>,
+    value_index: HashMap<TagValue, u32>,
+    features: Vec<Vec<u8>>,
+}
+
+impl LayerBuilder {
+    pub fn new(name: &str, extent: u32) -> Self {
+        Self {
+            name: name.to_string(),
+            extent,
+            keys: Vec::new(),
+            key_index: HashMap::new(),
+            values: Vec::new(),
+            value_index: HashMap::new(),
+            features: Vec::new(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.features.is_empty()
+    }
+
+    fn tags(&mut self, tags: Vec<(String, TagValue)>) -> Vec<u32> {
+        let mut indices = Vec::with_capacity(tags.len() * 2);
+        for (key, value) in tags {
+            let next_key = self.keys.len() as u32;
+            let key_index = *self.key_index.entry(key.clone()).or_insert(next_key);
+            if key_index == next_key {
+                self.keys.push(key);
+            }
+
+            let next_value = self.values.len() as u32;
+            let value_index = *self.value_index.entry(value.clone()).or_insert(next_value);
+            if value_index == next_value {
+                self.values.push(value);
+            }
+
+            indices.push(key_index);
+            indices.push(value_index);
+        }
+        indices
+    }
+
+    fn add_feature(&mut self, id: Option<u64>, kind: u64, geometry: Vec<u32>, tags: Vec<(String, TagValue)>) {
+        let tags = self.tags(tags);
+        let mut buf = Vec::new();
+        if let Some(id) = id {
+            write_tag(&mut buf, 1, WIRE_VARINT);
+            write_varint(&mut buf, id);
+        }
+        if !tags.is_empty() {
+            write_packed(&mut buf, 2, &tags);
+        }
+        write_tag(&mut buf, 3, WIRE_VARINT);
+        write_varint(&mut buf, kind);
+        write_packed(&mut buf, 4, &geometry);
+        self.features.push(buf);
+    }
+
+    // A point in tile coordinates
+    pub fn add_point(&mut self, id: Option<u64>, x: i32, y: i32, tags: Vec<(String, TagValue)>) {
+        let geometry = vec![command(CMD_MOVE_TO, 1), zigzag(x), zigzag(y)];
+        self.add_feature(id, GEOM_POINT, geometry, tags);
+    }
+
+    // A line in tile coordinates, repeated vertices are dropped. Returns false when nothing is left to draw.
+    pub fn add_line(&mut self, id: Option<u64>, points: &[(i32, i32)], tags: Vec<(String, TagValue)>) -> bool {
+        let mut line: Vec<(i32, i32)> = Vec::with_capacity(points.len());
+        for point in points {
+            if line.last() != Some(point) {
+                line.push(*point);
+            }
+        }
+        if line.len() < 2 {
+            return false;
+        }
+
+        let mut geometry = Vec::with_capacity(line.len() * 2 + 2);
+        geometry.push(command(CMD_MOVE_TO, 1));
+        geometry.push(zigzag(line[0].0));
+        geometry.push(zigzag(line[0].1));
+        geometry.push(command(CMD_LINE_TO, line.len() - 1));
+        for pair in line.windows(2) {
+            geometry.push(zigzag(pair[1].0 - pair[0].0));
+            geometry.push(zigzag(pair[1].1 - pair[0].1));
+        }
+
+        self.add_feature(id, GEOM_LINESTRING, geometry, tags);
+        true
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_tag(&mut buf, 15, WIRE_VARINT);
+        write_varint(&mut buf, 2);
+        write_bytes(&mut buf, 1, self.name.as_bytes());
+        for feature in &self.features {
+            write_bytes(&mut buf, 2, feature);
+        }
+        for key in &self.keys {
+            write_bytes(&mut buf, 3, key.as_bytes());
+        }
+        for value in &self.values {
+            write_bytes(&mut buf, 4, &value.encode());
+        }
+        write_tag(&mut buf, 5, WIRE_VARINT);
+        write_varint(&mut buf, self.extent as u64);
+        buf
+    }
+}
+
+// A tile made of the non-empty layers
+pub fn encode_tile(layers: &[LayerBuilder]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for layer in layers.iter().filter(|layer| !layer.is_empty()) {
+        write_bytes(&mut buf, 3, &layer.encode());
+    }
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn varint_zigzag_and_commands() {
+        let varint = |value: u64| {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, value);
+            buf
+        };
+        assert_eq!(varint(1), vec![0x01]);
+        assert_eq!(varint(127), vec![0x7f]);
+        assert_eq!(varint(300), vec![0xac, 0x02]);
+        assert_eq!(varint(4096), vec![0x80, 0x20]);
+
+        assert_eq!((zigzag(0), zigzag(-1), zigzag(1), zigzag(-2)), (0, 1, 2, 3));
+        assert_eq!(zigzag(i32::MAX), u32::MAX - 1);
+        assert_eq!(zigzag(i32::MIN), u32::MAX);
+
+        // The spec's examples: MoveTo(1) is 9, LineTo(3) is 26, ClosePath(1) is 15
+        assert_eq!(command(CMD_MOVE_TO, 1), 9);
+        assert_eq!(command(CMD_LINE_TO, 3), 26);
+        assert_eq!(command(7, 1), 15);
+    }
+
+    #[test]
+    fn point_tile_matches_the_spec_encoding() {
+        let mut layer = LayerBuilder::new("p", 4096);
+        layer.add_point(Some(1), 25, 17, vec![("a".to_string(), TagValue::Uint(1))]);
+        let feature = [0x08, 0x01, 0x12, 0x02, 0x00, 0x00, 0x18, 0x01, 0x22, 0x03, 0x09, 0x32, 0x22];
+
+        let mut expected = vec![0x1a, 30, 0x78, 0x02, 0x0a, 0x01, b'p', 0x12, 13];
+        expected.extend_from_slice(&feature);
+        expected.extend_from_slice(&[0x1a, 0x01, b'a', 0x22, 0x02, 0x28, 0x01, 0x28, 0x80, 0x20]);
+        assert_eq!(encode_tile(&[layer, LayerBuilder::new("empty", 4096)]), expected);
+    }
+
+    #[test]
+    fn lines_share_tags_and_skip_repeated_vertices() {
+        let mut layer = LayerBuilder::new("lines", 4096);
+        let tags = || vec![("kind".to_string(), TagValue::String("fiber".to_string())), ("weight".to_string(), TagValue::Double(0.5))];
+        // The spec's example line: MoveTo(2, 2) LineTo(2, 10) LineTo(10, 10)
+        assert!(layer.add_line(None, &[(2, 2), (2, 2), (2, 10), (10, 10)], tags()));
+        assert!(!layer.add_line(None, &[(5, 5), (5, 5)], tags()));
+        assert!(layer.add_line(Some(7), &[(0, 0), (-3, 4)], tags()));
+
+        assert_eq!(layer.features.len(), 2);
+        assert_eq!(layer.features[0], vec![0x12, 0x04, 0x00, 0x00, 0x01, 0x01, 0x18, 0x02, 0x22, 0x08, 9, 4, 4, 18, 0, 16, 16, 0]);
+        assert_eq!((layer.keys.len(), layer.values.len()), (2, 2));
+        assert_eq!(&layer.features[1][..2], &[0x08, 0x07]);
+    }
+}
@@ -0,0 +1,60 @@
+// bbox
+
+// Wrap a longitude into [-180, 180)
+pub fn wrap_lng(lng: f64) -> f64 {
+    ((lng + 180.0) % 360.0 + 360.0) % 360.0 - 180.0
+}
+
+// Split a viewport bbox into at most two bboxes that never cross the antimeridian.
+// Longitudes are wrapped, latitudes clamped, and a span of 360° or more becomes the whole world.
+// Same rules as supercluster.js `getClusters`.
+pub fn normalize_bbox(min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64) -> Vec<[f64; 4]> {
+    let min_lat = min_lat.clamp(-90.0, 90.0);
+    let max_lat = max_lat.clamp(-90.0, 90.0);
+
+    if max_lng - min_lng >= 360.0 {
+        return vec![[-180.0, min_lat, 180.0, max_lat]];
+    }
+
+    let west = wrap_lng(min_lng);
+    let east = if max_lng == 180.0 { 180.0 } else { wrap_lng(max_lng) };
+
+    if west > east {
+        // Viewport crosses ±180°, query the eastern and western halves separately
+        vec![
+            [west, min_lat, 180.0, max_lat],
+            [-180.0, min_lat, east, max_lat],
+        ]
+    } else {
+        vec![[west, min_lat, east, max_lat]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_lng_stays_in_range() {
+        assert_eq!(wrap_lng(0.0), 0.0);
+        assert_eq!(wrap_lng(190.0), -170.0);
+        assert_eq!(wrap_lng(-190.0), 170.0);
+        assert_eq!(wrap_lng(180.0), -180.0);
+        assert_eq!(wrap_lng(540.0), -180.0);
+        assert_eq!(wrap_lng(-720.0), 0.0);
+    }
+
+    #[test]
+    fn normalize_bbox_splits_at_the_antimeridian() {
+        assert_eq!(normalize_bbox(-10.0, -5.0, 10.0, 5.0), vec![[-10.0, -5.0, 10.0, 5.0]]);
+
+        // Crossing from the east: 170° to 190° is 170° to -170°
+        assert_eq!(normalize_bbox(170.0, -5.0, 190.0, 5.0), vec![[170.0, -5.0, 180.0, 5.0], [-180.0, -5.0, -170.0, 5.0]]);
+        // Crossing given as min_lng > max_lng
+        assert_eq!(normalize_bbox(170.0, -5.0, -170.0, 5.0), vec![[170.0, -5.0, 180.0, 5.0], [-180.0, -5.0, -170.0, 5.0]]);
+
+        // A full turn or more is the whole world, latitudes are clamped
+        assert_eq!(normalize_bbox(-200.0, -100.0, 200.0, 100.0), vec![[-180.0, -90.0, 180.0, 90.0]]);
+        assert_eq!(normalize_bbox(-180.0, 0.0, 180.0, 10.0), vec![[-180.0, 0.0, 180.0, 10.0]]);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod web_mercator;
+pub mod bbox;
+pub mod viewport;
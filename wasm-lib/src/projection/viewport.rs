@@ -0,0 +1,101 @@
+// viewport
+
+use serde::Serialize;
+use crate::projection::bbox::wrap_lng;
+use crate::projection::web_mercator::{lat_y, lng_x, x_lng, y_lat, TILE_SIZE};
+
+// Zoom used to frame a single point, the deepest zoom of Mapbox GL / MapLibre
+pub const MAX_FIT_ZOOM: f64 = 22.0;
+
+#[derive(Serialize, Clone, Copy, Debug)]
+pub struct Camera {
+    pub center: [f64; 2],
+    pub zoom: f64,
+    // The fitted bbox, min_lng > max_lng when it crosses the antimeridian
+    pub bbox: [f64; 4],
+}
+
+// Tightest bbox around lon / lat points. Longitudes are cut at the widest gap between them,
+// so points on both sides of ±180° give a bbox crossing the antimeridian (min_lng > max_lng).
+pub fn points_bbox(points: &[(f64, f64)]) -> Option<[f64; 4]> {
+    if points.is_empty() {
+        return None;
+    }
+
+    let mut lngs: Vec<f64> = points.iter().map(|point| wrap_lng(point.0)).collect();
+    lngs.sort_by(|a, b| a.total_cmp(b));
+    let (mut west, mut east) = (lngs[0], lngs[lngs.len() - 1]);
+    let mut widest = 360.0 - (east - west);
+    for pair in lngs.windows(2) {
+        if pair[1] - pair[0] > widest {
+            widest = pair[1] - pair[0];
+            west = pair[1];
+            east = pair[0];
+        }
+    }
+
+    let min_lat = points.iter().map(|point| point.1).fold(f64::INFINITY, f64::min);
+    let max_lat = points.iter().map(|point| point.1).fold(f64::NEG_INFINITY, f64::max);
+    Some([west, min_lat, east, max_lat])
+}
+
+// Center and zoom showing the bbox in a `width` x `height` pixel viewport with `padding` pixels
+// on every side, on the same 512 px tiles the clustering uses. The zoom is fractional and at
+// most MAX_FIT_ZOOM.
+pub fn fit_bbox(bbox: [f64; 4], width: f64, height: f64, padding: f64) -> Camera {
+    let [min_lng, min_lat, max_lng, max_lat] = bbox;
+    let x0 = lng_x(min_lng);
+    let mut span_x = lng_x(max_lng) - x0;
+    if span_x < 0.0 {
+        span_x += 1.0;
+    }
+    let (y0, y1) = (lat_y(max_lat), lat_y(min_lat));
+    let span_y = y1 - y0;
+
+    let available_x = (width - 2.0 * padding).max(1.0);
+    let available_y = (height - 2.0 * padding).max(1.0);
+    let scale = (available_x / (span_x * TILE_SIZE)).min(available_y / (span_y * TILE_SIZE));
+    let zoom = if scale.is_finite() { scale.log2().clamp(0.0, MAX_FIT_ZOOM) } else { MAX_FIT_ZOOM };
+
+    Camera {
+        center: [wrap_lng(x_lng(x0 + span_x / 2.0)), y_lat((y0 + y1) / 2.0)],
+        zoom,
+        bbox,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_bbox_cuts_at_the_widest_gap() {
+        assert_eq!(points_bbox(&[]), None);
+        assert_eq!(points_bbox(&[(10.0, 5.0)]), Some([10.0, 5.0, 10.0, 5.0]));
+        assert_eq!(points_bbox(&[(10.0, 5.0), (-20.0, -3.0), (40.0, 1.0)]), Some([-20.0, -3.0, 40.0, 5.0]));
+        // Both sides of ±180°, the bbox crosses the antimeridian
+        assert_eq!(points_bbox(&[(175.0, 0.0), (-170.0, 2.0), (179.0, -1.0)]), Some([175.0, -1.0, -170.0, 2.0]));
+        assert_eq!(points_bbox(&[(190.0, 0.0), (-175.0, 0.0)]), Some([-175.0, 0.0, -170.0, 0.0]));
+    }
+
+    #[test]
+    fn fit_bbox_frames_the_bbox() {
+        let camera = fit_bbox([-180.0, -85.0511287798066, 180.0, 85.0511287798066], TILE_SIZE, TILE_SIZE, 0.0);
+        assert!(camera.zoom.abs() < 1e-9);
+        assert!(camera.center[0].abs() < 1e-9 && camera.center[1].abs() < 1e-9);
+
+        // Half the world wide in a viewport twice the tile size
+        let camera = fit_bbox([0.0, -10.0, 180.0, 10.0], 2.0 * TILE_SIZE + 20.0, 2.0 * TILE_SIZE, 10.0);
+        assert!((camera.zoom - 2.0).abs() < 1e-9);
+        assert!((camera.center[0] - 90.0).abs() < 1e-9);
+
+        // Across the antimeridian the center sits on it, not on the opposite side of the world
+        let camera = fit_bbox([170.0, -5.0, -170.0, 5.0], 800.0, 600.0, 20.0);
+        assert!((camera.center[0].abs() - 180.0).abs() < 1e-9);
+        assert!(camera.zoom > 3.0);
+        assert_eq!(camera.bbox, [170.0, -5.0, -170.0, 5.0]);
+
+        // A single point gets the deepest zoom
+        assert_eq!(fit_bbox([10.0, 5.0, 10.0, 5.0], 800.0, 600.0, 20.0).zoom, MAX_FIT_ZOOM);
+    }
+}
@@ -0,0 +1,218 @@
+// web mercator
+
+use std::f64::consts::PI;
+use js_sys::{Float64Array, Uint32Array};
+use wasm_bindgen::prelude::*;
+
+// WGS84 semi-major axis, the sphere used by EPSG:3857
+pub const EARTH_RADIUS: f64 = 6378137.0;
+
+// Half of the equator length in Web Mercator meters
+pub const ORIGIN_SHIFT: f64 = PI * EARTH_RADIUS;
+
+// Pixel size of a tile at zoom 0. Pixel tolerances and cluster radii are measured in these pixels.
+pub const TILE_SIZE: f64 = 512.0;
+
+pub const MAX_LATITUDE: f64 = 85.0511287798066;
+
+// Normalized coordinates are the ones supercluster works with internally:
+// x and y in [0, 1], origin in the north-west corner of the world.
+pub fn lng_x(lng: f64) -> f64 {
+    lng / 360.0 + 0.5
+}
+
+pub fn lat_y(lat: f64) -> f64 {
+    let sin = (lat * PI / 180.0).sin();
+    let y = 0.5 - 0.25 * ((1.0 + sin) / (1.0 - sin)).ln() / PI;
+
+    // Poles project to infinity, clamp like supercluster does
+    y.clamp(0.0, 1.0)
+}
+
+pub fn x_lng(x: f64) -> f64 {
+    (x - 0.5) * 360.0
+}
+
+pub fn y_lat(y: f64) -> f64 {
+    let y2 = (180.0 - y * 360.0) * PI / 180.0;
+    360.0 * y2.exp().atan() / PI - 90.0
+}
+
+// World width in pixels at a (possibly fractional) zoom
+pub fn world_size(zoom: f64) -> f64 {
+    TILE_SIZE * 2f64.powf(zoom)
+}
+
+pub fn to_meters(lng: f64, lat: f64) -> (f64, f64) {
+    let x = (lng_x(lng) - 0.5) * 2.0 * ORIGIN_SHIFT;
+    let y = (0.5 - lat_y(lat)) * 2.0 * ORIGIN_SHIFT;
+    (x, y)
+}
+
+pub fn from_meters(x: f64, y: f64) -> (f64, f64) {
+    let lng = x_lng(x / (2.0 * ORIGIN_SHIFT) + 0.5);
+    let lat = y_lat(0.5 - y / (2.0 * ORIGIN_SHIFT));
+    (lng, lat)
+}
+
+pub fn to_pixel(lng: f64, lat: f64, zoom: f64) -> (f64, f64) {
+    let size = world_size(zoom);
+    (lng_x(lng) * size, lat_y(lat) * size)
+}
+
+pub fn from_pixel(x: f64, y: f64, zoom: f64) -> (f64, f64) {
+    let size = world_size(zoom);
+    (x_lng(x / size), y_lat(y / size))
+}
+
+// Slippy map tile (x, y) containing the point at integer zoom `z`
+pub fn tile_of(lng: f64, lat: f64, z: u32) -> (u32, u32) {
+    let n = 2f64.powi(z as i32);
+    let max = n - 1.0;
+    let x = (lng_x(lng) * n).floor().clamp(0.0, max);
+    let y = (lat_y(lat) * n).floor().clamp(0.0, max);
+    (x as u32, y as u32)
+}
+
+// Tile bounds as (min_lng, min_lat, max_lng, max_lat)
+pub fn tile_bbox(z: u32, x: u32, y: u32) -> (f64, f64, f64, f64) {
+    let n = 2f64.powi(z as i32);
+    let min_lng = x_lng(x as f64 / n);
+    let max_lng = x_lng((x + 1) as f64 / n);
+    let max_lat = y_lat(y as f64 / n);
+    let min_lat = y_lat((y + 1) as f64 / n);
+    (min_lng, min_lat, max_lng, max_lat)
+}
+
+// Ground resolution at a latitude, meters per pixel
+pub fn resolution(lat: f64, zoom: f64) -> f64 {
+    let lat = lat.clamp(-MAX_LATITUDE, MAX_LATITUDE);
+    (lat * PI / 180.0).cos() * 2.0 * ORIGIN_SHIFT / world_size(zoom)
+}
+
+// Squared pixel distance between two lon/lat points at `zoom`.
+// Takes the short way around, so points on both sides of ±180° are neighbours.
+pub fn pixel_distance_sq(a: (f64, f64), b: (f64, f64), zoom: f64) -> f64 {
+    let size = world_size(zoom);
+    let (ax, ay) = to_pixel(a.0, a.1, zoom);
+    let (bx, by) = to_pixel(b.0, b.1, zoom);
+    let dx = (ax - bx).rem_euclid(size);
+    let dx = dx.min(size - dx);
+    dx.powi(2) + (ay - by).powi(2)
+}
+
+fn pair_to_js_array(pair: (f64, f64)) -> Float64Array {
+    let array = Float64Array::new_with_length(2);
+    array.set_index(0, pair.0);
+    array.set_index(1, pair.1);
+    array
+}
+
+#[wasm_bindgen]
+pub fn lng_lat_to_meters(lng: f64, lat: f64) -> Float64Array {
+    pair_to_js_array(to_meters(lng, lat))
+}
+
+#[wasm_bindgen]
+pub fn meters_to_lng_lat(x: f64, y: f64) -> Float64Array {
+    pair_to_js_array(from_meters(x, y))
+}
+
+#[wasm_bindgen]
+pub fn lng_lat_to_pixel(lng: f64, lat: f64, zoom: f64) -> Float64Array {
+    pair_to_js_array(to_pixel(lng, lat, zoom))
+}
+
+#[wasm_bindgen]
+pub fn pixel_to_lng_lat(x: f64, y: f64, zoom: f64) -> Float64Array {
+    pair_to_js_array(from_pixel(x, y, zoom))
+}
+
+#[wasm_bindgen]
+pub fn meters_to_pixel(x: f64, y: f64, zoom: f64) -> Float64Array {
+    let (lng, lat) = from_meters(x, y);
+    pair_to_js_array(to_pixel(lng, lat, zoom))
+}
+
+#[wasm_bindgen]
+pub fn pixel_to_meters(x: f64, y: f64, zoom: f64) -> Float64Array {
+    let (lng, lat) = from_pixel(x, y, zoom);
+    pair_to_js_array(to_meters(lng, lat))
+}
+
+// Returns [x, y, z]
+#[wasm_bindgen]
+pub fn lng_lat_to_tile(lng: f64, lat: f64, zoom: u32) -> Uint32Array {
+    let (x, y) = tile_of(lng, lat, zoom);
+    let array = Uint32Array::new_with_length(3);
+    array.set_index(0, x);
+    array.set_index(1, y);
+    array.set_index(2, zoom);
+    array
+}
+
+// Returns [min_lng, min_lat, max_lng, max_lat]
+#[wasm_bindgen]
+pub fn tile_bounds(z: u32, x: u32, y: u32) -> Float64Array {
+    let (min_lng, min_lat, max_lng, max_lat) = tile_bbox(z, x, y);
+    let array = Float64Array::new_with_length(4);
+    array.set_index(0, min_lng);
+    array.set_index(1, min_lat);
+    array.set_index(2, max_lng);
+    array.set_index(3, max_lat);
+    array
+}
+
+#[wasm_bindgen]
+pub fn meters_per_pixel(lat: f64, zoom: f64) -> f64 {
+    resolution(lat, zoom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn projection_round_trips() {
+        for (lng, lat) in [(0.0, 0.0), (-122.4194, 37.7749), (151.2093, -33.8688), (179.9, 80.0)] {
+            let (x, y) = to_meters(lng, lat);
+            let (back_lng, back_lat) = from_meters(x, y);
+            assert!((back_lng - lng).abs() < 1e-9 && (back_lat - lat).abs() < 1e-9);
+
+            let (px, py) = to_pixel(lng, lat, 5.5);
+            let (back_lng, back_lat) = from_pixel(px, py, 5.5);
+            assert!((back_lng - lng).abs() < 1e-9 && (back_lat - lat).abs() < 1e-9);
+        }
+
+        assert_eq!(to_meters(180.0, 0.0).0, ORIGIN_SHIFT);
+        assert!(lat_y(MAX_LATITUDE).abs() < 1e-12);
+        assert_eq!(lat_y(90.0), 0.0);
+        assert_eq!(lat_y(-90.0), 1.0);
+    }
+
+    #[test]
+    fn tiles_cover_the_point() {
+        assert_eq!(tile_of(0.0, 0.0, 0), (0, 0));
+        assert_eq!(tile_of(-0.1, 0.1, 1), (0, 0));
+        assert_eq!(tile_of(0.1, -0.1, 1), (1, 1));
+        assert_eq!(tile_of(180.0, -90.0, 3), (7, 7));
+
+        let (min_lng, min_lat, max_lng, max_lat) = tile_bbox(0, 0, 0);
+        assert_eq!((min_lng, max_lng), (-180.0, 180.0));
+        assert!((max_lat - MAX_LATITUDE).abs() < 1e-9 && (min_lat + MAX_LATITUDE).abs() < 1e-9);
+
+        let (min_lng, min_lat, max_lng, max_lat) = tile_bbox(12, 2200, 1343);
+        let (x, y) = tile_of((min_lng + max_lng) / 2.0, (min_lat + max_lat) / 2.0, 12);
+        assert_eq!((x, y), (2200, 1343));
+    }
+
+    #[test]
+    fn pixel_distance_takes_the_short_way() {
+        // 512 px world at zoom 0, so 1° of longitude on the equator is 512 / 360 px
+        let degree = TILE_SIZE / 360.0;
+        assert!((pixel_distance_sq((10.0, 0.0), (11.0, 0.0), 0.0) - degree * degree).abs() < 1e-9);
+        assert!((pixel_distance_sq((179.5, 0.0), (-179.5, 0.0), 0.0) - degree * degree).abs() < 1e-9);
+        assert!((pixel_distance_sq((-179.5, 0.0), (179.5, 0.0), 1.0) - 4.0 * degree * degree).abs() < 1e-9);
+        assert!((resolution(0.0, 0.0) - 2.0 * ORIGIN_SHIFT / TILE_SIZE).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,381 @@
+// accumulators
+
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AccumulatorKind {
+    Sum,
+    Min,
+    Max,
+    Mean,
+    CountDistinct,
+    Histogram,
+    ThresholdCounter,
+    Severity,
+}
+
+// A threshold id on the severity scale of a `severity` accumulator
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SeverityLevel {
+    pub thr_id: i64,
+    // The id itself when omitted
+    #[serde(default)]
+    pub label: Option<String>,
+}
+
+// One rollup declared from JS, e.g. { name: "traffic", kind: "sum", property: "traffic" }
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AccumulatorDeclaration {
+    pub name: String,
+    pub kind: AccumulatorKind,
+    pub property: String,
+    // Ascending bin edges for `histogram`, values below the first edge land in bin 0
+    #[serde(default)]
+    pub bins: Vec<f64>,
+    // Threshold ids from least to most severe for `severity`. Ids not listed rank below the listed ones,
+    // lower ids less severe, so without a scale a higher `thr_id` is worse.
+    #[serde(default)]
+    pub severity: Vec<SeverityLevel>,
+}
+
+impl AccumulatorDeclaration {
+    // What `SuperclusterWrapper::new` used to hard-code. Vertices without a `thr_id` are left out of its
+    // counts, where a `severity` accumulator counts them as `no_threshold`.
+    pub fn threshold_counter() -> Self {
+        Self {
+            name: "threshold_counter".to_string(),
+            kind: AccumulatorKind::ThresholdCounter,
+            property: "thr_id".to_string(),
+            bins: Vec::new(),
+            severity: Vec::new(),
+        }
+    }
+
+    // Position on the severity scale, greater is more severe
+    pub fn severity_rank(&self, thr_id: i64) -> (bool, i64) {
+        match self.severity.iter().position(|level| level.thr_id == thr_id) {
+            Some(position) => (true, position as i64),
+            None => (false, thr_id),
+        }
+    }
+
+    fn severity_label(&self, thr_id: i64) -> String {
+        self.severity.iter()
+            .find(|level| level.thr_id == thr_id)
+            .and_then(|level| level.label.clone())
+            .unwrap_or_else(|| thr_id.to_string())
+    }
+
+    fn severity_summary(&self, counts: &HashMap<i64, usize>, missing: usize) -> SeveritySummary {
+        let total = counts.values().sum::<usize>() + missing;
+        let percentage = |count: usize| if total > 0 { count as f64 * 100.0 / total as f64 } else { 0.0 };
+
+        let mut thr_ids: Vec<i64> = counts.keys().copied().filter(|thr_id| counts[thr_id] > 0).collect();
+        thr_ids.sort_by_key(|thr_id| std::cmp::Reverse(self.severity_rank(*thr_id)));
+        let worst = thr_ids.first().copied();
+        // Ties go to the more severe threshold
+        let dominant = thr_ids.iter().copied().max_by(|a, b| {
+            counts[a].cmp(&counts[b]).then(self.severity_rank(*a).cmp(&self.severity_rank(*b)))
+        });
+
+        SeveritySummary {
+            worst,
+            worst_label: worst.map(|thr_id| self.severity_label(thr_id)),
+            dominant,
+            dominant_label: dominant.map(|thr_id| self.severity_label(thr_id)),
+            breakdown: thr_ids.iter()
+                .map(|thr_id| SeverityShare {
+                    thr_id: *thr_id,
+                    label: self.severity_label(*thr_id),
+                    count: counts[thr_id],
+                    percentage: percentage(counts[thr_id]),
+                })
+                .collect(),
+            no_threshold: missing,
+            no_threshold_percentage: percentage(missing),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum AccumulatorState {
+    Sum(f64),
+    Min(Option<f64>),
+    Max(Option<f64>),
+    Mean { sum: f64, count: usize },
+    CountDistinct(HashSet<u64>),
+    Histogram(Vec<usize>),
+    ThresholdCounter(HashMap<i64, usize>),
+    // counts per threshold id and the number of vertices without one
+    Severity { counts: HashMap<i64, usize>, missing: usize },
+}
+
+#[derive(Serialize, Clone)]
+pub struct SeverityShare {
+    pub thr_id: i64,
+    pub label: String,
+    pub count: usize,
+    pub percentage: f64,
+}
+
+// Result of a `severity` accumulator, percentages are of every vertex including those without a threshold
+#[derive(Serialize, Clone)]
+pub struct SeveritySummary {
+    pub worst: Option<i64>,
+    pub worst_label: Option<String>,
+    // the most frequent threshold
+    pub dominant: Option<i64>,
+    pub dominant_label: Option<String>,
+    // most severe first
+    pub breakdown: Vec<SeverityShare>,
+    pub no_threshold: usize,
+    pub no_threshold_percentage: f64,
+}
+
+#[derive(Serialize, Clone)]
+#[serde(untagged)]
+pub enum StatisticValue {
+    Number(Option<f64>),
+    Count(usize),
+    Bins(Vec<usize>),
+    Counts(HashMap<i64, usize>),
+    Severity(SeveritySummary),
+}
+
+fn threshold_id(value: f64) -> Option<i64> {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        Some(value as i64)
+    } else {
+        None
+    }
+}
+
+impl AccumulatorState {
+    // NaN and infinities cannot be written to a snapshot
+    pub fn is_finite(&self) -> bool {
+        match self {
+            AccumulatorState::Sum(sum) | AccumulatorState::Mean { sum, .. } => sum.is_finite(),
+            AccumulatorState::Min(value) | AccumulatorState::Max(value) => value.is_none_or(f64::is_finite),
+            _ => true,
+        }
+    }
+
+    pub fn empty(declaration: &AccumulatorDeclaration) -> Self {
+        match declaration.kind {
+            AccumulatorKind::Sum => AccumulatorState::Sum(0.0),
+            AccumulatorKind::Min => AccumulatorState::Min(None),
+            AccumulatorKind::Max => AccumulatorState::Max(None),
+            AccumulatorKind::Mean => AccumulatorState::Mean { sum: 0.0, count: 0 },
+            AccumulatorKind::CountDistinct => AccumulatorState::CountDistinct(HashSet::new()),
+            AccumulatorKind::Histogram => AccumulatorState::Histogram(vec![0; declaration.bins.len() + 1]),
+            AccumulatorKind::ThresholdCounter => AccumulatorState::ThresholdCounter(HashMap::new()),
+            AccumulatorKind::Severity => AccumulatorState::Severity { counts: HashMap::new(), missing: 0 },
+        }
+    }
+
+    // State of a single point, vertices without the property contribute nothing except to the
+    // no-threshold count of `severity`
+    pub fn from_value(declaration: &AccumulatorDeclaration, value: Option<f64>) -> Self {
+        let mut state = Self::empty(declaration);
+        let value = match value {
+            Some(value) if value.is_finite() => value,
+            _ => {
+                if let AccumulatorState::Severity { missing, .. } = &mut state {
+                    *missing = 1;
+                }
+                return state;
+            }
+        };
+
+        match &mut state {
+            AccumulatorState::Sum(sum) => *sum = value,
+            AccumulatorState::Min(min) => *min = Some(value),
+            AccumulatorState::Max(max) => *max = Some(value),
+            AccumulatorState::Mean { sum, count } => {
+                *sum = value;
+                *count = 1;
+            }
+            AccumulatorState::CountDistinct(values) => {
+                values.insert(value.to_bits());
+            }
+            AccumulatorState::Histogram(bins) => {
+                let bin = declaration.bins.iter().take_while(|edge| value >= **edge).count();
+                bins[bin] += 1;
+            }
+            // Threshold ids are integers, anything else is no threshold rather than a truncated id
+            AccumulatorState::ThresholdCounter(counts) => {
+                if let Some(thr_id) = threshold_id(value) {
+                    counts.insert(thr_id, 1);
+                }
+            }
+            AccumulatorState::Severity { counts, missing } => match threshold_id(value) {
+                Some(thr_id) => {
+                    counts.insert(thr_id, 1);
+                }
+                None => *missing = 1,
+            },
+        }
+
+        state
+    }
+
+    pub fn merge(&mut self, other: &AccumulatorState) {
+        match (self, other) {
+            (AccumulatorState::Sum(a), AccumulatorState::Sum(b)) => *a += b,
+            (AccumulatorState::Min(a), AccumulatorState::Min(b)) => {
+                *a = match (*a, *b) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                }
+            }
+            (AccumulatorState::Max(a), AccumulatorState::Max(b)) => {
+                *a = match (*a, *b) {
+                    (Some(a), Some(b)) => Some(a.max(b)),
+                    (a, b) => a.or(b),
+                }
+            }
+            (AccumulatorState::Mean { sum, count }, AccumulatorState::Mean { sum: other_sum, count: other_count }) => {
+                *sum += other_sum;
+                *count += other_count;
+            }
+            (AccumulatorState::CountDistinct(a), AccumulatorState::CountDistinct(b)) => a.extend(b.iter()),
+            (AccumulatorState::Histogram(a), AccumulatorState::Histogram(b)) => {
+                for (bin, count) in a.iter_mut().zip(b.iter()) {
+                    *bin += count;
+                }
+            }
+            (AccumulatorState::ThresholdCounter(a), AccumulatorState::ThresholdCounter(b)) => {
+                for (key, count) in b {
+                    *a.entry(*key).or_insert(0) += count;
+                }
+            }
+            (AccumulatorState::Severity { counts, missing }, AccumulatorState::Severity { counts: other_counts, missing: other_missing }) => {
+                for (key, count) in other_counts {
+                    *counts.entry(*key).or_insert(0) += count;
+                }
+                *missing += other_missing;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn result(&self, declaration: &AccumulatorDeclaration) -> StatisticValue {
+        match self {
+            AccumulatorState::Sum(sum) => StatisticValue::Number(Some(*sum)),
+            AccumulatorState::Min(min) => StatisticValue::Number(*min),
+            AccumulatorState::Max(max) => StatisticValue::Number(*max),
+            AccumulatorState::Mean { sum, count } => StatisticValue::Number(if *count > 0 { Some(sum / *count as f64) } else { None }),
+            AccumulatorState::CountDistinct(values) => StatisticValue::Count(values.len()),
+            AccumulatorState::Histogram(bins) => StatisticValue::Bins(bins.clone()),
+            AccumulatorState::ThresholdCounter(counts) => StatisticValue::Counts(counts.clone()),
+            AccumulatorState::Severity { counts, missing } => StatisticValue::Severity(declaration.severity_summary(counts, *missing)),
+        }
+    }
+}
+
+pub fn merge_states(target: &mut [AccumulatorState], other: &[AccumulatorState]) {
+    for (state, other) in target.iter_mut().zip(other.iter()) {
+        state.merge(other);
+    }
+}
+
+pub fn statistics(declarations: &[AccumulatorDeclaration], states: &[AccumulatorState]) -> HashMap<String, StatisticValue> {
+    declarations.iter()
+        .zip(states.iter())
+        .map(|(declaration, state)| (declaration.name.clone(), state.result(declaration)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn declaration(kind: AccumulatorKind, bins: Vec<f64>) -> AccumulatorDeclaration {
+        AccumulatorDeclaration { name: "test".to_string(), kind, property: "value".to_string(), bins, severity: Vec::new() }
+    }
+
+    fn accumulate(declaration: &AccumulatorDeclaration, values: &[Option<f64>]) -> StatisticValue {
+        let mut state = AccumulatorState::empty(declaration);
+        for value in values {
+            state.merge(&AccumulatorState::from_value(declaration, *value));
+        }
+        state.result(declaration)
+    }
+
+    #[test]
+    fn accumulators_merge_point_states() {
+        let values = [Some(4.0), None, Some(1.0), Some(f64::NAN), Some(4.0), Some(7.0)];
+        let number = |kind| match accumulate(&declaration(kind, Vec::new()), &values) {
+            StatisticValue::Number(number) => number,
+            _ => panic!("expected a number"),
+        };
+        assert_eq!(number(AccumulatorKind::Sum), Some(16.0));
+        assert_eq!(number(AccumulatorKind::Min), Some(1.0));
+        assert_eq!(number(AccumulatorKind::Max), Some(7.0));
+        assert_eq!(number(AccumulatorKind::Mean), Some(4.0));
+
+        // Nothing to average or compare without values
+        let empty = declaration(AccumulatorKind::Mean, Vec::new());
+        assert!(matches!(accumulate(&empty, &[None]), StatisticValue::Number(None)));
+        assert!(matches!(accumulate(&declaration(AccumulatorKind::Min, Vec::new()), &[]), StatisticValue::Number(None)));
+
+        assert!(matches!(accumulate(&declaration(AccumulatorKind::CountDistinct, Vec::new()), &values), StatisticValue::Count(3)));
+        match accumulate(&declaration(AccumulatorKind::Histogram, vec![2.0, 5.0]), &values) {
+            StatisticValue::Bins(bins) => assert_eq!(bins, vec![1, 2, 1]),
+            _ => panic!("expected bins"),
+        }
+    }
+
+    #[test]
+    fn threshold_counter_skips_non_integer_ids() {
+        let counter = AccumulatorDeclaration::threshold_counter();
+        match accumulate(&counter, &[Some(1.0), Some(2.0), Some(2.0), Some(2.5), None, Some(-1.0)]) {
+            StatisticValue::Counts(counts) => {
+                assert_eq!(counts.len(), 3);
+                assert_eq!((counts[&1], counts[&2], counts[&-1]), (1, 2, 1));
+            }
+            _ => panic!("expected counts"),
+        }
+
+        // Merging states of different kinds leaves the target alone
+        let mut state = AccumulatorState::Sum(1.0);
+        state.merge(&AccumulatorState::Max(Some(5.0)));
+        assert!(matches!(state, AccumulatorState::Sum(sum) if sum == 1.0));
+    }
+
+    #[test]
+    fn severity_ranks_on_the_declared_scale() {
+        let mut severity = declaration(AccumulatorKind::Severity, Vec::new());
+        severity.severity = vec![
+            SeverityLevel { thr_id: 3, label: Some("ok".to_string()) },
+            SeverityLevel { thr_id: 1, label: Some("warning".to_string()) },
+            SeverityLevel { thr_id: 2, label: Some("critical".to_string()) },
+        ];
+        let values = [Some(3.0), Some(3.0), Some(1.0), Some(1.0), Some(9.0), None, Some(0.5), Some(2.0)];
+        let summary = match accumulate(&severity, &values) {
+            StatisticValue::Severity(summary) => summary,
+            _ => panic!("expected a severity summary"),
+        };
+
+        // 9 is not on the scale so it ranks below every listed id
+        assert_eq!(summary.worst, Some(2));
+        assert_eq!(summary.worst_label.as_deref(), Some("critical"));
+        // 3 and 1 both count twice, the tie goes to the more severe 1
+        assert_eq!(summary.dominant, Some(1));
+        assert_eq!(summary.breakdown.iter().map(|share| share.thr_id).collect::<Vec<i64>>(), vec![2, 1, 3, 9]);
+        assert_eq!(summary.breakdown[3].label, "9");
+        // The missing value and the non-integer one have no threshold, percentages are of all eight
+        assert_eq!(summary.no_threshold, 2);
+        assert_eq!(summary.no_threshold_percentage, 25.0);
+        assert_eq!(summary.breakdown[1].percentage, 25.0);
+
+        // Without a scale higher ids are worse
+        severity.severity.clear();
+        match accumulate(&severity, &values) {
+            StatisticValue::Severity(summary) => assert_eq!((summary.worst, summary.dominant), (Some(9), Some(3))),
+            _ => panic!("expected a severity summary"),
+        }
+    }
+}
@@ -0,0 +1,240 @@
+// features
+
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use serde_wasm_bindgen::Serializer;
+use hypergraph::VertexIndex;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::supercluster::SuperclusterWrapper;
+use crate::supercluster::levels::LevelItem;
+use crate::projection::web_mercator::{lat_y, lng_x, TILE_SIZE};
+
+// supercluster.js' abbreviation: 999, 1.2k, 35k
+pub fn abbreviate(count: usize) -> Value {
+    if count >= 10000 {
+        json!(format!("{}k", (count as f64 / 1000.0).round()))
+    } else if count >= 1000 {
+        json!(format!("{}k", (count as f64 / 100.0).round() / 10.0))
+    } else {
+        json!(count)
+    }
+}
+
+// Plain objects rather than `Map`s, which is what GeoJSON consumers expect
+fn to_js_object<T: Serialize>(value: &T) -> JsValue {
+    value.serialize(&Serializer::new().serialize_maps_as_objects(true)).unwrap()
+}
+
+// A GeoJSON-vt style tile feature: type 1 is a point, coordinates in tile units
+#[derive(Serialize)]
+pub struct TileFeature {
+    #[serde(rename = "type")]
+    pub kind: u8,
+    pub geometry: Vec<[f64; 2]>,
+    pub tags: Map<String, Value>,
+    pub id: usize,
+}
+
+#[derive(Serialize)]
+pub struct Tile {
+    pub features: Vec<TileFeature>,
+}
+
+impl SuperclusterWrapper {
+    // `cluster`, `cluster_id`, `point_count`, `point_count_abbreviated` and the accumulated statistics
+    fn cluster_properties(&self, item: &LevelItem) -> Map<String, Value> {
+        let mut properties = Map::new();
+        for (name, value) in self.wrap_cluster(item).statistics {
+            properties.insert(name, serde_json::to_value(value).unwrap_or(Value::Null));
+        }
+        properties.insert("cluster".to_string(), json!(true));
+        properties.insert("cluster_id".to_string(), json!(item.id));
+        properties.insert("point_count".to_string(), json!(item.count()));
+        properties.insert("point_count_abbreviated".to_string(), abbreviate(item.count()));
+        properties
+    }
+
+    // The vertex as it was handed in: `thr_id`, the properties set from JS and its vertex index
+    fn leaf_properties(&self, graph: &GraphWrapper, source: usize) -> Map<String, Value> {
+        let vertex = self.vertices[source];
+        let mut properties = Map::new();
+        if let Some(values) = graph.properties.get(&vertex) {
+            for (key, value) in values {
+                properties.insert(key.clone(), json!(value));
+            }
+        }
+        properties.insert("thr_id".to_string(), json!(graph.graph.get_vertex_weight(VertexIndex(vertex)).ok().and_then(|node| node.thr_id)));
+        properties.insert("vertex_index".to_string(), json!(vertex));
+        properties
+    }
+
+    fn cluster_feature(&self, item: &LevelItem) -> Value {
+        let (lng, lat) = item.lng_lat();
+        json!({
+            "type": "Feature",
+            "id": item.id,
+            "properties": self.cluster_properties(item),
+            "geometry": { "type": "Point", "coordinates": [lng, lat] },
+        })
+    }
+
+    fn leaf_feature(&self, graph: &GraphWrapper, source: usize) -> Value {
+        let (lng, lat) = self.points[source];
+        json!({
+            "type": "Feature",
+            "id": self.node_ids[source],
+            "properties": self.leaf_properties(graph, source),
+            "geometry": { "type": "Point", "coordinates": [lng, lat] },
+        })
+    }
+
+    fn item_feature(&self, graph: &GraphWrapper, item: &LevelItem) -> Value {
+        if self.levels.is_cluster(item.id) {
+            self.cluster_feature(item)
+        } else {
+            self.leaf_feature(graph, item.id)
+        }
+    }
+
+    fn add_tile_features(&self, graph: &GraphWrapper, items: Vec<&LevelItem>, x: f64, y: f64, z2: f64, tile: &mut Tile) {
+        for item in items {
+            let cluster = self.levels.is_cluster(item.id);
+            let ((px, py), tags, id) = if cluster {
+                (item.xy(), self.cluster_properties(item), item.id)
+            } else {
+                let (lng, lat) = self.points[item.id];
+                ((lng_x(lng), lat_y(lat)), self.leaf_properties(graph, item.id), self.node_ids[item.id])
+            };
+
+            tile.features.push(TileFeature {
+                kind: 1,
+                geometry: vec![[(TILE_SIZE * (px * z2 - x)).round(), (TILE_SIZE * (py * z2 - y)).round()]],
+                tags,
+                id,
+            });
+        }
+    }
+
+    // Port of supercluster.js `getTile`, including the points wrapped around the antimeridian
+    pub fn geojson_tile(&self, graph: &GraphWrapper, z: usize, x: u32, y: u32) -> Option<Tile> {
+        let z2 = 2f64.powi(z as i32);
+        let p = self.levels.radius / TILE_SIZE;
+        let (x, y) = (x as f64, y as f64);
+        let top = (y - p) / z2;
+        let bottom = (y + 1.0 + p) / z2;
+
+        let mut tile = Tile { features: Vec::new() };
+        self.add_tile_features(graph, self.levels.items_in_rect(z, (x - p) / z2, top, (x + 1.0 + p) / z2, bottom), x, y, z2, &mut tile);
+        if x == 0.0 {
+            self.add_tile_features(graph, self.levels.items_in_rect(z, 1.0 - p / z2, top, 1.0, bottom), z2, y, z2, &mut tile);
+        }
+        if x == z2 - 1.0 {
+            self.add_tile_features(graph, self.levels.items_in_rect(z, 0.0, top, p / z2, bottom), -1.0, y, z2, &mut tile);
+        }
+
+        if tile.features.is_empty() {
+            None
+        } else {
+            Some(tile)
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // Same output as supercluster.js `getClusters`: GeoJSON point Features, clusters carrying
+    // `cluster`, `cluster_id`, `point_count`, `point_count_abbreviated`, leaves their vertex properties
+    #[wasm_bindgen]
+    pub fn get_geojson_clusters(&self, graph: &GraphWrapper, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> JsValue {
+        let features: Vec<Value> = self.clusters_in_bbox(min_lng, min_lat, max_lng, max_lat, zoom)
+            .into_iter()
+            .map(|item| self.item_feature(graph, item))
+            .collect();
+
+        to_js_object(&features)
+    }
+
+    // supercluster.js `getChildren`
+    #[wasm_bindgen]
+    pub fn get_geojson_children(&self, graph: &GraphWrapper, cluster_id: usize) -> Result<JsValue, JsValue> {
+        let children = self.levels.children(cluster_id);
+        if children.is_empty() {
+            return Err(JsValue::from_str("No cluster with the specified id."));
+        }
+
+        let features: Vec<Value> = children.into_iter()
+            .map(|item| self.item_feature(graph, item))
+            .collect();
+        Ok(to_js_object(&features))
+    }
+
+    // supercluster.js `getLeaves`, limit defaults to 10
+    #[wasm_bindgen]
+    pub fn get_geojson_leaves(&self, graph: &GraphWrapper, cluster_id: usize, limit: Option<usize>, offset: Option<usize>) -> Result<JsValue, JsValue> {
+        let leaves = self.leaves_page(cluster_id, limit.unwrap_or(10), offset.unwrap_or(0))
+            .map_err(|e| JsValue::from_str(&e))?;
+
+        let features: Vec<Value> = leaves.iter()
+            .map(|leaf| self.leaf_feature(graph, leaf.source_id))
+            .collect();
+        Ok(to_js_object(&features))
+    }
+
+    // supercluster.js `getTile`: { features: [{ type: 1, geometry: [[x, y]], tags, id }] } or null
+    #[wasm_bindgen]
+    pub fn get_geojson_tile(&self, graph: &GraphWrapper, z: usize, x: u32, y: u32) -> JsValue {
+        match self.geojson_tile(graph, z, x, y) {
+            Some(tile) => to_js_object(&tile),
+            None => JsValue::NULL,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_are_abbreviated_like_supercluster() {
+        assert_eq!(abbreviate(999), json!(999));
+        assert_eq!(abbreviate(1000), json!("1k"));
+        assert_eq!(abbreviate(1234), json!("1.2k"));
+        assert_eq!(abbreviate(9999), json!("10k"));
+        assert_eq!(abbreviate(35400), json!("35k"));
+    }
+
+    #[test]
+    fn features_carry_supercluster_properties() {
+        use crate::supercluster::fixtures::{graph_of, index_of};
+        use crate::supercluster::supercluster::ClusterOptions;
+
+        let mut graph = graph_of([10.0, 10.01, 10.02, 179.99].map(|lon| ((lon, -10.0), Some(2))));
+        graph.set_vertex_property(3, "capacity".to_string(), 7.0);
+        let index = index_of(&graph, ClusterOptions::default());
+
+        let cluster = index.levels.find_item(index.levels.level(0).visible[0]).unwrap();
+        let feature = index.item_feature(&graph, cluster);
+        assert_eq!(feature["type"], json!("Feature"));
+        assert_eq!(feature["id"], json!(cluster.id));
+        let properties = &feature["properties"];
+        assert_eq!(properties["cluster"], json!(true));
+        assert_eq!(properties["cluster_id"], json!(cluster.id));
+        assert_eq!(properties["point_count"], json!(3));
+        assert_eq!(properties["point_count_abbreviated"], json!(3));
+        assert_eq!(properties["threshold_counter"], json!({ "2": 3 }));
+
+        let feature = index.item_feature(&graph, index.levels.find_item(3).unwrap());
+        assert_eq!(feature["geometry"]["coordinates"], json!([179.99, -10.0]));
+        assert_eq!(feature["properties"], json!({ "capacity": 7.0, "thr_id": 2, "vertex_index": 3 }));
+
+        // The point by the antimeridian is in the tiles on both of its sides, left of the western one
+        let east = index.geojson_tile(&graph, 1, 1, 1).unwrap();
+        let west = index.geojson_tile(&graph, 1, 0, 1).unwrap();
+        let edge_x = |tile: &Tile| tile.features.iter().find(|feature| feature.id == 3).unwrap().geometry[0][0];
+        assert!((edge_x(&east) - TILE_SIZE).abs() < 1.0);
+        assert!(edge_x(&west).abs() < 1.0 && edge_x(&west) <= 0.0);
+        assert!(west.features.iter().all(|feature| feature.kind == 1));
+        assert!(index.geojson_tile(&graph, 3, 2, 0).is_none());
+    }
+}
@@ -0,0 +1,144 @@
+// filters
+
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use hypergraph::{HyperedgeIndex, VertexIndex};
+use crate::graph::suzaku_graph::GraphWrapper;
+
+// Inclusive bounds on a vertex property, an open side when omitted
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PropertyRange {
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+impl PropertyRange {
+    fn contains(&self, value: f64) -> bool {
+        self.min.is_none_or(|min| value >= min) && self.max.is_none_or(|max| value <= max)
+    }
+}
+
+// Selects the vertices an index clusters, every condition has to hold, e.g.
+// { thr_ids: [2, 3, null], properties: { traffic: { min: 100 } }, relation_kinds: ["fiber"] }
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct ClusterFilter {
+    // `null` in the list selects vertices without a threshold
+    #[serde(default)]
+    pub thr_ids: Option<Vec<Option<i32>>>,
+    // A vertex missing the property does not match
+    #[serde(default)]
+    pub properties: HashMap<String, PropertyRange>,
+    // Vertices taking part in at least one relation of these kinds
+    #[serde(default)]
+    pub relation_kinds: Option<Vec<String>>,
+}
+
+impl ClusterFilter {
+    // Vertices of every relation whose kind is in `kinds`
+    fn related_vertices(graph: &GraphWrapper, kinds: &[String]) -> HashSet<usize> {
+        let mut vertices = HashSet::new();
+        for (kind, hyperedge) in graph.relations.values() {
+            if !kinds.contains(kind) {
+                continue;
+            }
+            if let Ok(members) = graph.graph.get_hyperedge_vertices(HyperedgeIndex(*hyperedge)) {
+                vertices.extend(members.iter().map(|vertex| vertex.0));
+            }
+        }
+        vertices
+    }
+
+    fn matches_vertex(&self, graph: &GraphWrapper, vertex: usize) -> bool {
+        if let Some(thr_ids) = &self.thr_ids {
+            let thr_id = match graph.graph.get_vertex_weight(VertexIndex(vertex)) {
+                Ok(node) => node.thr_id,
+                Err(_) => return false,
+            };
+            if !thr_ids.contains(&thr_id) {
+                return false;
+            }
+        }
+
+        self.properties.iter().all(|(key, range)| {
+            graph.vertex_property(vertex, key).is_some_and(|value| range.contains(value))
+        })
+    }
+
+    // Whether one of the relations the vertex takes part in is of a kind in `kinds`
+    fn has_relation(graph: &GraphWrapper, kinds: &[String], vertex: usize) -> bool {
+        let hyperedges = match graph.graph.get_vertex_hyperedges(VertexIndex(vertex)) {
+            Ok(hyperedges) => hyperedges,
+            Err(_) => return false,
+        };
+        hyperedges.iter().any(|hyperedge| {
+            graph.graph.get_hyperedge_weight(*hyperedge)
+                .ok()
+                .and_then(|relation| graph.relations.get(&relation.id()))
+                .is_some_and(|(kind, _)| kinds.contains(kind))
+        })
+    }
+
+    // One vertex, looking at its own relations only
+    pub fn matches(&self, graph: &GraphWrapper, vertex: usize) -> bool {
+        if !self.matches_vertex(graph, vertex) {
+            return false;
+        }
+        match &self.relation_kinds {
+            Some(kinds) => Self::has_relation(graph, kinds, vertex),
+            None => true,
+        }
+    }
+
+    // Keep the matching vertices, collecting the relation kinds once for the whole list
+    pub fn select(&self, graph: &GraphWrapper, vertices: Vec<usize>) -> Vec<usize> {
+        let related = self.relation_kinds.as_ref().map(|kinds| Self::related_vertices(graph, kinds));
+
+        vertices.into_iter()
+            .filter(|vertex| related.as_ref().is_none_or(|related| related.contains(vertex)))
+            .filter(|vertex| self.matches_vertex(graph, *vertex))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter_graph() -> GraphWrapper {
+        use crate::supercluster::fixtures::graph_of;
+
+        let thr_ids = [Some(1), Some(2), None, Some(1), Some(3)];
+        let mut graph = graph_of(thr_ids.iter().enumerate().map(|(id, thr_id)| ((id as f64, 0.0), *thr_id)));
+        for id in 0..thr_ids.len() {
+            graph.set_vertex_property(id as u32, "traffic".to_string(), id as f64 * 50.0);
+        }
+        graph.create_relation(10, 0, 1, "fiber".to_string(), 1.0, None).unwrap();
+        graph.create_relation(11, 2, 3, "copper".to_string(), 1.0, None).unwrap();
+        graph.create_relation(12, 3, 4, "fiber".to_string(), 1.0, None).unwrap();
+        graph
+    }
+
+    #[test]
+    fn matches_agrees_with_select() {
+        let graph = filter_graph();
+        let filters = [
+            ClusterFilter { thr_ids: Some(vec![Some(1), None]), ..Default::default() },
+            ClusterFilter { relation_kinds: Some(vec!["copper".to_string()]), ..Default::default() },
+            ClusterFilter {
+                properties: vec![("traffic".to_string(), PropertyRange { min: Some(50.0), max: Some(150.0) })].into_iter().collect(),
+                relation_kinds: Some(vec!["fiber".to_string()]),
+                ..Default::default()
+            },
+        ];
+        let expected: [&[usize]; 3] = [&[0, 2, 3], &[2, 3], &[1, 3]];
+
+        for (filter, expected) in filters.iter().zip(expected) {
+            assert_eq!(filter.select(&graph, (0..5).collect()), expected);
+            let matching: Vec<usize> = (0..5).filter(|vertex| filter.matches(&graph, *vertex)).collect();
+            assert_eq!(matching, expected);
+        }
+        assert!(!filters[1].matches(&graph, 9));
+    }
+}
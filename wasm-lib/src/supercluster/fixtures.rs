@@ -0,0 +1,49 @@
+// fixtures
+
+use crate::graph::suzaku_graph::{Coords, GraphWrapper, Node};
+use crate::supercluster::levels::ClusterLevels;
+use crate::supercluster::supercluster::{ClusterOptions, SuperclusterWrapper};
+
+/// A graph of one node per point, numbered from 0 in order
+pub fn graph_of(points: impl IntoIterator<Item = ((f64, f64), Option<i32>)>) -> GraphWrapper {
+    let mut graph = GraphWrapper::new();
+    for (id, ((lon, lat), thr_id)) in points.into_iter().enumerate() {
+        graph.graph.add_vertex(Node::new(id, Coords { lon, lat }, thr_id, true)).unwrap();
+    }
+    graph
+}
+
+/// The index the tests cluster with, zooms 0 to 16 with a 40 px radius
+pub fn index_of(graph: &GraphWrapper, options: ClusterOptions) -> SuperclusterWrapper {
+    SuperclusterWrapper::build(graph, 16, 40.0, options)
+}
+
+pub fn assert_same_levels(actual: &ClusterLevels, expected: &ClusterLevels) {
+    assert_eq!(actual.levels.len(), expected.levels.len());
+    for (actual, expected) in actual.levels.iter().zip(&expected.levels) {
+        assert_eq!(actual.visible, expected.visible, "zoom {}", actual.zoom);
+        let mut ids: Vec<&usize> = actual.items.keys().collect();
+        ids.sort_unstable();
+        let mut expected_ids: Vec<&usize> = expected.items.keys().collect();
+        expected_ids.sort_unstable();
+        assert_eq!(ids, expected_ids, "zoom {}", actual.zoom);
+        for (id, item) in &actual.items {
+            assert_eq!(item, &expected.items[id], "zoom {}", actual.zoom);
+        }
+    }
+}
+
+pub fn scattered_points(count: usize, seed: u64) -> Vec<(f64, f64)> {
+    // Points in a few tight groups around a 4° square, from a fixed linear congruential sequence
+    let mut state = seed;
+    let mut next = move || {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    };
+    (0..count)
+        .map(|_| {
+            let (group_x, group_y) = ((next() * 4.0).floor(), (next() * 4.0).floor());
+            (group_x + next() * next(), group_y + next() * next())
+        })
+        .collect()
+}
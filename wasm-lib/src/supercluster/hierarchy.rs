@@ -0,0 +1,217 @@
+// hierarchy
+
+use std::collections::{BTreeMap, HashMap};
+use wasm_bindgen::prelude::*;
+use serde_wasm_bindgen::to_value;
+use js_sys::{Float64Array, Uint32Array, Uint8Array};
+use crate::supercluster::SuperclusterWrapper;
+use crate::supercluster::accumulators::{statistics, StatisticValue};
+use crate::supercluster::levels::NO_ITEM;
+
+// Parent id of the items at the lowest zoom
+pub const NO_PARENT: u32 = u32::MAX;
+
+// Every cluster and point at every zoom as columns, one row per (zoom, id), ordered by zoom then id.
+// An item that stays unchanged over several zooms has one row per zoom, its own id as parent.
+#[wasm_bindgen]
+pub struct ClusterHierarchy {
+    ids: Vec<u32>,
+    parent_ids: Vec<u32>,
+    zooms: Vec<u8>,
+    counts: Vec<u32>,
+    clusters: Vec<u8>,
+    positions: Vec<f64>,
+    // one column per numeric statistic, bins / counts split into `name_<bin>` / `name_<key>` columns,
+    // severity summaries into `name_worst`, `name_dominant`, `name_<thr_id>` and `name_no_threshold`
+    statistics: BTreeMap<String, Vec<f64>>,
+}
+
+impl ClusterHierarchy {
+    fn push_statistics(&mut self, row: usize, values: HashMap<String, StatisticValue>) {
+        let mut cells: Vec<(String, f64)> = Vec::new();
+        for (name, value) in values {
+            match value {
+                StatisticValue::Number(value) => cells.push((name, value.unwrap_or(f64::NAN))),
+                StatisticValue::Count(count) => cells.push((name, count as f64)),
+                StatisticValue::Bins(bins) => {
+                    for (bin, count) in bins.into_iter().enumerate() {
+                        cells.push((format!("{}_{}", name, bin), count as f64));
+                    }
+                }
+                StatisticValue::Counts(counts) => {
+                    for (key, count) in counts {
+                        cells.push((format!("{}_{}", name, key), count as f64));
+                    }
+                }
+                StatisticValue::Severity(summary) => {
+                    cells.push((format!("{}_worst", name), summary.worst.map_or(f64::NAN, |worst| worst as f64)));
+                    cells.push((format!("{}_dominant", name), summary.dominant.map_or(f64::NAN, |dominant| dominant as f64)));
+                    for share in summary.breakdown {
+                        cells.push((format!("{}_{}", name, share.thr_id), share.count as f64));
+                    }
+                    cells.push((format!("{}_no_threshold", name), summary.no_threshold as f64));
+                }
+            }
+        }
+
+        // Columns first seen on a later row are zero for the rows before it
+        for (name, value) in cells {
+            let column = self.statistics.entry(name).or_insert_with(|| vec![0.0; row]);
+            column.push(value);
+        }
+        for column in self.statistics.values_mut() {
+            column.resize(row + 1, 0.0);
+        }
+    }
+}
+
+impl SuperclusterWrapper {
+    pub fn hierarchy(&self) -> ClusterHierarchy {
+        let mut hierarchy = ClusterHierarchy {
+            ids: Vec::new(),
+            parent_ids: Vec::new(),
+            zooms: Vec::new(),
+            counts: Vec::new(),
+            clusters: Vec::new(),
+            positions: Vec::new(),
+            statistics: BTreeMap::new(),
+        };
+
+        for (index, level) in self.levels.levels.iter().enumerate() {
+            let parent_level = index.checked_sub(1).map(|parent| &self.levels.levels[parent]);
+            let mut ids: Vec<&usize> = level.items.keys().collect();
+            ids.sort();
+
+            for id in ids {
+                let item = &level.items[id];
+                let parent = parent_level
+                    .and_then(|parent| parent.visible.get(item.members[0]))
+                    .filter(|parent| **parent != NO_ITEM)
+                    .map_or(NO_PARENT, |parent| *parent as u32);
+                let (lng, lat) = item.lng_lat();
+
+                let row = hierarchy.ids.len();
+                hierarchy.ids.push(item.id as u32);
+                hierarchy.parent_ids.push(parent);
+                hierarchy.zooms.push(level.zoom as u8);
+                hierarchy.counts.push(item.count() as u32);
+                hierarchy.clusters.push(self.levels.is_cluster(item.id) as u8);
+                hierarchy.positions.push(lng);
+                hierarchy.positions.push(lat);
+                hierarchy.push_statistics(row, statistics(&self.accumulators, &self.cluster_states(item.id)));
+            }
+        }
+
+        hierarchy
+    }
+}
+
+#[wasm_bindgen]
+impl ClusterHierarchy {
+    #[wasm_bindgen]
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    #[wasm_bindgen]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    #[wasm_bindgen]
+    pub fn get_ids(&self) -> Uint32Array {
+        Uint32Array::from(&self.ids[..])
+    }
+
+    // `NO_PARENT` (0xffffffff) at the lowest zoom
+    #[wasm_bindgen]
+    pub fn get_parent_ids(&self) -> Uint32Array {
+        Uint32Array::from(&self.parent_ids[..])
+    }
+
+    #[wasm_bindgen]
+    pub fn get_zooms(&self) -> Uint8Array {
+        Uint8Array::from(&self.zooms[..])
+    }
+
+    #[wasm_bindgen]
+    pub fn get_counts(&self) -> Uint32Array {
+        Uint32Array::from(&self.counts[..])
+    }
+
+    // 1 for clusters, 0 for single points
+    #[wasm_bindgen]
+    pub fn get_clusters(&self) -> Uint8Array {
+        Uint8Array::from(&self.clusters[..])
+    }
+
+    // lon, lat per row
+    #[wasm_bindgen]
+    pub fn get_positions(&self) -> Float64Array {
+        Float64Array::from(&self.positions[..])
+    }
+
+    #[wasm_bindgen]
+    pub fn get_statistic_names(&self) -> JsValue {
+        let names: Vec<&String> = self.statistics.keys().collect();
+        to_value(&names).unwrap()
+    }
+
+    // NaN where a min / max / mean or a severity's worst / dominant had no values
+    #[wasm_bindgen]
+    pub fn get_statistic(&self, name: &str) -> Option<Float64Array> {
+        self.statistics.get(name).map(|column| Float64Array::from(&column[..]))
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // The whole cluster tree for animating zoom transitions, see `ClusterHierarchy`
+    #[wasm_bindgen]
+    pub fn get_hierarchy(&self) -> ClusterHierarchy {
+        self.hierarchy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hierarchy_rows_link_every_zoom_to_the_one_below() {
+        use crate::supercluster::fixtures::{graph_of, index_of, scattered_points};
+        use crate::supercluster::supercluster::ClusterOptions;
+
+        let graph = graph_of(scattered_points(60, 11).into_iter().enumerate().map(|(id, point)| (point, Some((id % 3) as i32))));
+        let index = index_of(&graph, ClusterOptions::default());
+        let hierarchy = index.hierarchy();
+        let rows = index.levels.levels.iter().map(|level| level.items.len()).sum::<usize>();
+        assert_eq!(hierarchy.len(), rows);
+        assert_eq!(hierarchy.positions.len(), 2 * rows);
+        assert!(hierarchy.statistics.values().all(|column| column.len() == rows));
+        assert!(hierarchy.zooms.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        for row in 0..rows {
+            let zoom = hierarchy.zooms[row] as usize;
+            let id = hierarchy.ids[row] as usize;
+            let item = index.levels.level(zoom).items[&id].clone();
+            assert_eq!(hierarchy.counts[row] as usize, item.count());
+            assert_eq!(hierarchy.clusters[row] == 1, index.levels.is_cluster(id));
+            if zoom == 0 {
+                assert_eq!(hierarchy.parent_ids[row], NO_PARENT);
+                continue;
+            }
+            // The parent one zoom down holds all of the item's members
+            let parent = &index.levels.level(zoom - 1).items[&(hierarchy.parent_ids[row] as usize)];
+            assert!(item.members.iter().all(|member| parent.members.contains(member)));
+        }
+
+        // Every point is counted once per zoom, in the threshold columns too
+        for zoom in 0..=17u8 {
+            let at_zoom = (0..rows).filter(|row| hierarchy.zooms[*row] == zoom);
+            assert_eq!(at_zoom.clone().map(|row| hierarchy.counts[row]).sum::<u32>(), 60);
+            let thresholds: f64 = hierarchy.statistics.values().map(|column| at_zoom.clone().map(|row| column[row]).sum::<f64>()).sum();
+            assert_eq!(thresholds, 60.0);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+// indexes
+
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use serde_wasm_bindgen::to_value;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::supercluster::SuperclusterWrapper;
+
+// Several clusterings of the same graph kept side by side, e.g. one per severity or relation kind,
+// so toggling a legend entry only switches the index that `get_clusters` reads
+#[wasm_bindgen]
+pub struct ClusterIndexSet {
+    indexes: HashMap<String, SuperclusterWrapper>,
+    active: Option<String>,
+}
+
+impl ClusterIndexSet {
+    fn index(&self, name: Option<String>) -> Result<&SuperclusterWrapper, JsValue> {
+        let name = name.or_else(|| self.active.clone())
+            .ok_or_else(|| JsValue::from_str("No index name given and no active index"))?;
+        self.indexes.get(&name)
+            .ok_or_else(|| JsValue::from_str(&format!("No cluster index named {}", name)))
+    }
+}
+
+#[wasm_bindgen]
+impl ClusterIndexSet {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self { indexes: HashMap::new(), active: None }
+    }
+
+    // Build (or replace) the index `name`, `options` as in `create_supercluster_with_options`,
+    // usually with a filter: { filter: { thr_ids: [3] } }. The first index added becomes active.
+    #[wasm_bindgen]
+    pub fn add_index(&mut self, graph: &GraphWrapper, name: String, max_zoom: usize, radius: f64, options: JsValue) -> Result<(), JsValue> {
+        let index = SuperclusterWrapper::with_options(graph, max_zoom, radius, options)?;
+        if self.active.is_none() {
+            self.active = Some(name.clone());
+        }
+        self.indexes.insert(name, index);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_index(&mut self, name: &str) -> bool {
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+        self.indexes.remove(name).is_some()
+    }
+
+    #[wasm_bindgen]
+    pub fn has_index(&self, name: &str) -> bool {
+        self.indexes.contains_key(name)
+    }
+
+    #[wasm_bindgen]
+    pub fn get_index_names(&self) -> JsValue {
+        let mut names: Vec<&String> = self.indexes.keys().collect();
+        names.sort();
+        to_value(&names).unwrap()
+    }
+
+    #[wasm_bindgen]
+    pub fn set_active(&mut self, name: String) -> Result<(), JsValue> {
+        if !self.indexes.contains_key(&name) {
+            return Err(JsValue::from_str(&format!("No cluster index named {}", name)));
+        }
+        self.active = Some(name);
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_active(&self) -> Option<String> {
+        self.active.clone()
+    }
+
+    // Clusters of the index `name`, or of the active index when `name` is undefined
+    #[wasm_bindgen]
+    pub fn get_clusters(&self, name: Option<String>, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> Result<JsValue, JsValue> {
+        Ok(self.index(name)?.get_clusters(min_lng, min_lat, max_lng, max_lat, zoom))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_cluster_expansion_zoom(&self, name: Option<String>, cluster_id: usize) -> Result<usize, JsValue> {
+        self.index(name)?.get_cluster_expansion_zoom(cluster_id)
+    }
+
+    #[wasm_bindgen]
+    pub fn get_cluster_info(&self, name: Option<String>, cluster_id: usize, zoom: usize) -> Result<JsValue, JsValue> {
+        Ok(self.index(name)?.get_cluster_info(cluster_id, zoom))
+    }
+
+    #[wasm_bindgen]
+    pub fn get_leaves(&self, name: Option<String>, cluster_id: usize, limit: Option<usize>, offset: Option<usize>) -> Result<JsValue, JsValue> {
+        self.index(name)?.get_leaves(cluster_id, limit, offset)
+    }
+
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_cluster_edges(&self, name: Option<String>, graph: &GraphWrapper, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> Result<JsValue, JsValue> {
+        Ok(self.index(name)?.get_cluster_edges(graph, min_lng, min_lat, max_lng, max_lat, zoom))
+    }
+
+    // Apply a vertex change to every index, each one re-checks its own filter
+    #[wasm_bindgen]
+    pub fn sync_vertex(&mut self, graph: &GraphWrapper, vertex_index: u32) {
+        for index in self.indexes.values_mut() {
+            index.sync_vertex(graph, vertex_index);
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_vertex(&mut self, vertex_index: u32) {
+        for index in self.indexes.values_mut() {
+            index.remove_vertex(vertex_index);
+        }
+    }
+}
+
+impl Default for ClusterIndexSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
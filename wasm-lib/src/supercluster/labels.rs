@@ -0,0 +1,347 @@
+// labels
+
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_wasm_bindgen::{from_value, to_value};
+use crate::projection::bbox::wrap_lng;
+use crate::projection::web_mercator::{lng_x, world_size};
+use crate::supercluster::SuperclusterWrapper;
+use crate::supercluster::accumulators::{AccumulatorKind, AccumulatorState};
+use crate::supercluster::levels::LevelItem;
+
+// Side of the point a label is put on, tried in this order
+const ANCHORS: [&str; 8] = ["top-right", "top-left", "bottom-right", "bottom-left", "right", "left", "top", "bottom"];
+
+// Grid cell for the placed boxes, in pixels
+const LABEL_CELL: f64 = 128.0;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum LabelPriority {
+    PointCount,
+    // Worst threshold of a `severity` accumulator first, then `point_count`
+    Severity,
+    TotalWeight,
+}
+
+fn default_priority() -> LabelPriority {
+    LabelPriority::PointCount
+}
+
+fn default_size() -> [f64; 2] {
+    [80.0, 18.0]
+}
+
+fn default_offset() -> f64 {
+    4.0
+}
+
+// Box size of one label, in pixels
+#[derive(Deserialize, Clone, Debug)]
+pub struct LabelSize {
+    pub id: usize,
+    pub width: f64,
+    pub height: f64,
+}
+
+// e.g. { priority: "severity", severity: "status", sizes: [{ id: 12, width: 120, height: 18 }], marker_radius: 10 }
+#[derive(Deserialize)]
+pub struct LabelOptions {
+    #[serde(default = "default_priority")]
+    pub priority: LabelPriority,
+    // Name of the `severity` accumulator ranking the labels, the first one declared when omitted
+    #[serde(default)]
+    pub severity: Option<String>,
+    // Label sizes per cluster / point id, `default_size` for the others
+    #[serde(default)]
+    pub sizes: Vec<LabelSize>,
+    #[serde(default = "default_size")]
+    pub default_size: [f64; 2],
+    // Gap between the point and its label
+    #[serde(default = "default_offset")]
+    pub offset: f64,
+    // Minimum gap between two labels
+    #[serde(default)]
+    pub padding: f64,
+    // Markers drawn as circles of this radius keep labels off them, 0 ignores markers
+    #[serde(default)]
+    pub marker_radius: f64,
+}
+
+impl Default for LabelOptions {
+    fn default() -> Self {
+        Self {
+            priority: default_priority(),
+            severity: None,
+            sizes: Vec::new(),
+            default_size: default_size(),
+            offset: default_offset(),
+            padding: 0.0,
+            marker_radius: 0.0,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct PlacedLabel {
+    pub id: usize,
+    pub cluster: bool,
+    pub point_count: usize,
+    pub position: [f64; 2],
+    pub anchor: &'static str,
+    // Top-left corner of the label box relative to the point, in pixels, y down
+    pub offset: [f64; 2],
+    pub width: f64,
+    pub height: f64,
+}
+
+// Axis-aligned box in pixels, x relative to the west edge of the queried bbox
+#[derive(Clone, Copy)]
+struct LabelBox {
+    min_x: f64,
+    min_y: f64,
+    max_x: f64,
+    max_y: f64,
+}
+
+impl LabelBox {
+    fn intersects(&self, other: &LabelBox) -> bool {
+        self.min_x < other.max_x && other.min_x < self.max_x && self.min_y < other.max_y && other.min_y < self.max_y
+    }
+}
+
+// Placed boxes indexed by the grid cells they cover
+#[derive(Default)]
+struct Occupied {
+    boxes: Vec<LabelBox>,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl Occupied {
+    fn cells_of(bounds: &LabelBox) -> impl Iterator<Item = (i64, i64)> {
+        let (min_cx, min_cy) = ((bounds.min_x / LABEL_CELL).floor() as i64, (bounds.min_y / LABEL_CELL).floor() as i64);
+        let (max_cx, max_cy) = ((bounds.max_x / LABEL_CELL).floor() as i64, (bounds.max_y / LABEL_CELL).floor() as i64);
+        (min_cx..=max_cx).flat_map(move |cx| (min_cy..=max_cy).map(move |cy| (cx, cy)))
+    }
+
+    fn collides(&self, bounds: &LabelBox) -> bool {
+        Self::cells_of(bounds).any(|cell| {
+            self.cells.get(&cell).is_some_and(|indices| indices.iter().any(|index| self.boxes[*index].intersects(bounds)))
+        })
+    }
+
+    fn insert(&mut self, bounds: LabelBox) {
+        let index = self.boxes.len();
+        for cell in Self::cells_of(&bounds) {
+            self.cells.entry(cell).or_default().push(index);
+        }
+        self.boxes.push(bounds);
+    }
+}
+
+// Top-left corner of a `width` x `height` box put on `anchor`'s side of the point, `gap` pixels away
+fn anchor_offset(anchor: &str, width: f64, height: f64, gap: f64) -> (f64, f64) {
+    let x = match anchor {
+        "top-right" | "bottom-right" | "right" => gap,
+        "top-left" | "bottom-left" | "left" => -gap - width,
+        _ => -width / 2.0,
+    };
+    let y = match anchor {
+        "top-right" | "top-left" | "top" => -gap - height,
+        "bottom-right" | "bottom-left" | "bottom" => gap,
+        _ => -height / 2.0,
+    };
+    (x, y)
+}
+
+impl SuperclusterWrapper {
+    // Most severe threshold of a cluster on the scale of the `severity` accumulator at `index`
+    fn worst_severity(&self, item: &LevelItem, index: usize) -> Option<(bool, i64)> {
+        let states = self.cluster_states(item.id);
+        match states.get(index) {
+            Some(AccumulatorState::Severity { counts, .. }) => counts.iter()
+                .filter(|(_, count)| **count > 0)
+                .map(|(thr_id, _)| self.accumulators[index].severity_rank(*thr_id))
+                .max(),
+            _ => None,
+        }
+    }
+
+    // Greedy placement over the clusters and points in the bbox: highest priority first, each label on the
+    // first of the eight anchors where it overlaps no label (or marker) placed before, dropped otherwise.
+    // Ranking by severity needs the `severity` accumulator it names, or any when it names none.
+    pub fn label_placement(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize, options: &LabelOptions) -> Result<Vec<PlacedLabel>, String> {
+        let severity_index = match options.priority {
+            LabelPriority::Severity => {
+                let index = self.accumulators.iter().position(|declaration| {
+                    declaration.kind == AccumulatorKind::Severity
+                        && options.severity.as_ref().is_none_or(|name| *name == declaration.name)
+                });
+                match (index, &options.severity) {
+                    (Some(index), _) => Some(index),
+                    (None, Some(name)) => return Err(format!("No severity accumulator named {}", name)),
+                    (None, None) => return Err("Severity priority needs a severity accumulator".to_string()),
+                }
+            }
+            _ => None,
+        };
+
+        let mut items: Vec<(Option<(bool, i64)>, &LevelItem)> = self.clusters_in_bbox(min_lng, min_lat, max_lng, max_lat, zoom)
+            .into_iter()
+            .map(|item| (severity_index.and_then(|index| self.worst_severity(item, index)), item))
+            .collect();
+        items.sort_by(|(a_severity, a), (b_severity, b)| {
+            let weight = |item: &LevelItem| if options.priority == LabelPriority::TotalWeight { item.weight } else { 0.0 };
+            b_severity.cmp(a_severity)
+                .then(weight(b).total_cmp(&weight(a)))
+                .then(b.count().cmp(&a.count()))
+                .then(a.id.cmp(&b.id))
+        });
+        let items: Vec<&LevelItem> = items.into_iter().map(|(_, item)| item).collect();
+
+        // Pixels at `zoom`, x measured east from the bbox's west edge so the antimeridian does not split it
+        let size = world_size(zoom as f64);
+        let west = lng_x(wrap_lng(min_lng)) * size;
+        let project = |item: &LevelItem| {
+            let (x, y) = item.xy();
+            ((x * size - west).rem_euclid(size), y * size)
+        };
+        let sizes: HashMap<usize, [f64; 2]> = options.sizes.iter().map(|size| (size.id, [size.width, size.height])).collect();
+        let padding = options.padding / 2.0;
+
+        let mut occupied = Occupied::default();
+        if options.marker_radius > 0.0 {
+            let radius = options.marker_radius;
+            for item in &items {
+                let (x, y) = project(item);
+                occupied.insert(LabelBox { min_x: x - radius, min_y: y - radius, max_x: x + radius, max_y: y + radius });
+            }
+        }
+
+        let mut labels = Vec::new();
+        for item in items {
+            let (x, y) = project(item);
+            let [width, height] = sizes.get(&item.id).copied().unwrap_or(options.default_size);
+            let gap = options.offset + options.marker_radius.max(0.0);
+
+            for anchor in ANCHORS {
+                let (dx, dy) = anchor_offset(anchor, width, height, gap);
+                let bounds = LabelBox {
+                    min_x: x + dx - padding,
+                    min_y: y + dy - padding,
+                    max_x: x + dx + width + padding,
+                    max_y: y + dy + height + padding,
+                };
+                if occupied.collides(&bounds) {
+                    continue;
+                }
+
+                occupied.insert(bounds);
+                let (lng, lat) = item.lng_lat();
+                labels.push(PlacedLabel {
+                    id: item.id,
+                    cluster: self.levels.is_cluster(item.id),
+                    point_count: item.count(),
+                    position: [lng, lat],
+                    anchor,
+                    offset: [dx, dy],
+                    width,
+                    height,
+                });
+                break;
+            }
+        }
+
+        Ok(labels)
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // Labels of the clusters and points in the bbox that fit without overlapping, see `LabelOptions`.
+    // Each comes with the anchor it was placed on and the pixel offset of its box from the point.
+    #[wasm_bindgen]
+    pub fn place_labels(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize, options: JsValue) -> Result<JsValue, JsValue> {
+        let options: LabelOptions = if options.is_undefined() || options.is_null() {
+            LabelOptions::default()
+        } else {
+            from_value(options).map_err(|e| JsValue::from_str(&format!("Invalid label options: {:?}", e)))?
+        };
+
+        match self.label_placement(min_lng, min_lat, max_lng, max_lat, zoom, &options) {
+            Ok(labels) => Ok(to_value(&labels).unwrap()),
+            Err(e) => Err(JsValue::from_str(&e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn label_index(accumulators: Vec<crate::supercluster::accumulators::AccumulatorDeclaration>) -> SuperclusterWrapper {
+        use crate::supercluster::fixtures::{graph_of, index_of};
+        use crate::supercluster::supercluster::ClusterOptions;
+
+        // A 5 x 5 grid of points about 56 px apart at zoom 17, closer than a label is wide
+        let graph = graph_of((0..25).map(|id| ((10.0 + (id % 5) as f64 * 0.0003, 45.0 + (id / 5) as f64 * 0.0002), Some(id % 3))));
+        index_of(&graph, ClusterOptions { accumulators, ..Default::default() })
+    }
+
+    #[test]
+    fn placed_labels_never_overlap() {
+        use crate::projection::web_mercator::lat_y;
+
+        let index = label_index(vec![crate::supercluster::accumulators::AccumulatorDeclaration::threshold_counter()]);
+        let options = LabelOptions { padding: 2.0, marker_radius: 6.0, ..Default::default() };
+        let labels = index.label_placement(9.0, 44.0, 11.0, 46.0, 17, &options).unwrap();
+        assert!(!labels.is_empty() && labels.len() < 25);
+
+        let size = world_size(17.0);
+        let boxes: Vec<LabelBox> = labels.iter()
+            .map(|label| {
+                let (x, y) = (lng_x(label.position[0]) * size + label.offset[0], lat_y(label.position[1]) * size + label.offset[1]);
+                LabelBox { min_x: x, min_y: y, max_x: x + label.width, max_y: y + label.height }
+            })
+            .collect();
+        for (i, a) in boxes.iter().enumerate() {
+            assert!(boxes[i + 1..].iter().all(|b| !a.intersects(b)));
+            // Nor on any marker
+            for label in &labels {
+                let (x, y) = (lng_x(label.position[0]) * size, lat_y(label.position[1]) * size);
+                let marker = LabelBox { min_x: x - 6.0, min_y: y - 6.0, max_x: x + 6.0, max_y: y + 6.0 };
+                assert!(!a.intersects(&marker));
+            }
+        }
+
+        // Zoomed out the grid is one cluster, labeled on the first anchor
+        let labels = index.label_placement(9.0, 44.0, 11.0, 46.0, 3, &options).unwrap();
+        assert_eq!(labels.len(), 1);
+        assert_eq!((labels[0].cluster, labels[0].point_count, labels[0].anchor), (true, 25, "top-right"));
+    }
+
+    #[test]
+    fn severity_priority_needs_its_accumulator() {
+        use crate::supercluster::accumulators::{AccumulatorDeclaration, SeverityLevel};
+
+        let mut status = AccumulatorDeclaration::threshold_counter();
+        status.name = "status".to_string();
+        status.kind = AccumulatorKind::Severity;
+        status.severity = vec![2, 1, 0].into_iter().map(|thr_id| SeverityLevel { thr_id, label: None }).collect();
+
+        let options = |severity: Option<&str>| LabelOptions { priority: LabelPriority::Severity, severity: severity.map(str::to_string), ..Default::default() };
+        let index = label_index(vec![AccumulatorDeclaration::threshold_counter()]);
+        assert!(index.label_placement(9.0, 44.0, 11.0, 46.0, 17, &options(None)).is_err());
+
+        let index = label_index(vec![AccumulatorDeclaration::threshold_counter(), status]);
+        assert!(index.label_placement(9.0, 44.0, 11.0, 46.0, 17, &options(Some("other"))).is_err());
+
+        // thr_id 0 is the most severe on the scale, those points get their labels first
+        let labels = index.label_placement(9.0, 44.0, 11.0, 46.0, 17, &options(Some("status"))).unwrap();
+        assert_eq!(labels[0].id % 3, 0);
+        assert_eq!(labels[0].anchor, "top-right");
+        let same = index.label_placement(9.0, 44.0, 11.0, 46.0, 17, &options(None)).unwrap();
+        assert_eq!(labels.iter().map(|label| label.id).collect::<Vec<usize>>(), same.iter().map(|label| label.id).collect::<Vec<usize>>());
+    }
+}
@@ -0,0 +1,744 @@
+// levels
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use crate::projection::bbox::normalize_bbox;
+use crate::projection::web_mercator::{lat_y, lng_x, x_lng, y_lat, TILE_SIZE};
+use crate::supercluster::topology::Topology;
+
+pub const NO_ITEM: usize = usize::MAX;
+
+// Cluster ids keep the zoom they were formed at in their low 5 bits, like supercluster's
+pub const MAX_ZOOM: usize = 30;
+
+// Zero-weight points still count a little so a cluster of them keeps a centroid
+const MIN_CENTROID_WEIGHT: f64 = 1e-9;
+
+pub fn centroid_weight(weight: f64) -> f64 {
+    weight.max(MIN_CENTROID_WEIGHT)
+}
+
+// A cluster or a single point as it is visible at one zoom
+#[derive(Clone, PartialEq, Debug)]
+pub struct LevelItem {
+    pub id: usize,
+    // ascending source ids
+    pub members: Vec<usize>,
+    // weighted sums of the members' normalized Web Mercator coordinates
+    pub sum_x: f64,
+    pub sum_y: f64,
+    sum_w: f64,
+    // sum of the members' weights
+    pub weight: f64,
+    // ascending ids of the items one zoom up it was clustered from, empty for points
+    pub children: Vec<usize>,
+    cell: (i64, i64),
+}
+
+impl LevelItem {
+    pub fn count(&self) -> usize {
+        self.members.len()
+    }
+
+    // Normalized centroid, the weighted mean of the members (the plain mean with unit weights,
+    // like supercluster computes it). Items one zoom down are clustered by these centroids.
+    pub fn xy(&self) -> (f64, f64) {
+        if self.sum_w <= 0.0 {
+            return (self.sum_x, self.sum_y);
+        }
+        (self.sum_x / self.sum_w, self.sum_y / self.sum_w)
+    }
+
+    pub fn lng_lat(&self) -> (f64, f64) {
+        let (x, y) = self.xy();
+        (x_lng(x), y_lat(y))
+    }
+}
+
+// NO_ITEM is written as -1 so serialized levels move between 32 and 64-bit builds
+mod visible_ids {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use super::NO_ITEM;
+
+    pub fn serialize<S: Serializer>(visible: &[usize], serializer: S) -> Result<S::Ok, S::Error> {
+        let ids: Vec<i64> = visible.iter().map(|id| if *id == NO_ITEM { -1 } else { *id as i64 }).collect();
+        ids.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<usize>, D::Error> {
+        let ids = Vec::<i64>::deserialize(deserializer)?;
+        Ok(ids.into_iter().map(|id| if id < 0 { NO_ITEM } else { id as usize }).collect())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ClusterLevel {
+    pub zoom: usize,
+    // only `visible` is serialized, `restore` rebuilds the items and the grid from it
+    #[serde(skip)]
+    pub items: HashMap<usize, LevelItem>,
+    // item id per source, NO_ITEM for removed sources
+    #[serde(with = "visible_ids")]
+    pub visible: Vec<usize>,
+    // grid over item centroids, one cell per cluster radius
+    #[serde(skip)]
+    cell_size: f64,
+    #[serde(skip)]
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl ClusterLevel {
+    fn new(zoom: usize, cell_size: f64, sources: usize) -> Self {
+        Self { zoom, items: HashMap::new(), visible: vec![NO_ITEM; sources], cell_size, cells: HashMap::new() }
+    }
+
+    fn cell_of(&self, x: f64, y: f64) -> (i64, i64) {
+        ((x / self.cell_size).floor() as i64, (y / self.cell_size).floor() as i64)
+    }
+
+    // Sums are taken in member order, so the same members always give the same centroid
+    fn insert_item(&mut self, id: usize, mut members: Vec<usize>, points: &[(f64, f64)], weights: &[f64]) {
+        members.sort_unstable();
+        let mut item = LevelItem { id, members: Vec::with_capacity(members.len()), sum_x: 0.0, sum_y: 0.0, sum_w: 0.0, weight: 0.0, children: Vec::new(), cell: (0, 0) };
+        for member in members {
+            let (lng, lat) = points[member];
+            let w = centroid_weight(weights[member]);
+            item.sum_x += w * lng_x(lng);
+            item.sum_y += w * lat_y(lat);
+            item.sum_w += w;
+            item.weight += weights[member];
+            item.members.push(member);
+            self.visible[member] = id;
+        }
+        self.file_item(item);
+    }
+
+    // Cluster of `children`, items of the zoom above. Sums are taken in child order, so the same children
+    // always give the same centroid.
+    fn insert_group(&mut self, id: usize, children: Vec<usize>, above: &ClusterLevel) {
+        let mut item = LevelItem { id, members: Vec::new(), sum_x: 0.0, sum_y: 0.0, sum_w: 0.0, weight: 0.0, children: Vec::new(), cell: (0, 0) };
+        for child in &children {
+            let child = &above.items[child];
+            item.sum_x += child.sum_x;
+            item.sum_y += child.sum_y;
+            item.sum_w += child.sum_w;
+            item.weight += child.weight;
+            item.members.extend_from_slice(&child.members);
+        }
+        // a stable sort merges the children's runs of ascending members in linear time or so
+        item.members.sort();
+        for member in &item.members {
+            self.visible[*member] = id;
+        }
+        item.children = children;
+        self.file_item(item);
+    }
+
+    // Put an item into the grid cell of its centroid
+    fn file_item(&mut self, mut item: LevelItem) {
+        let (x, y) = item.xy();
+        item.cell = self.cell_of(x, y);
+        self.cells.entry(item.cell).or_default().push(item.id);
+        self.items.insert(item.id, item);
+    }
+
+    fn remove_item(&mut self, id: usize) -> Option<LevelItem> {
+        let item = self.items.remove(&id)?;
+        if let Some(ids) = self.cells.get_mut(&item.cell) {
+            ids.retain(|other| *other != id);
+            if ids.is_empty() {
+                self.cells.remove(&item.cell);
+            }
+        }
+        Some(item)
+    }
+
+    // Items whose centroid lies inside the normalized rectangle
+    fn items_in_rect(&self, min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Vec<&LevelItem> {
+        let (min_cx, min_cy) = self.cell_of(min_x, min_y);
+        let (max_cx, max_cy) = self.cell_of(max_x, max_y);
+        let cell_count = (max_cx - min_cx + 1) as f64 * (max_cy - min_cy + 1) as f64;
+        let inside = |item: &&LevelItem| {
+            let (x, y) = item.xy();
+            x >= min_x && x <= max_x && y >= min_y && y <= max_y
+        };
+
+        // Wide views cover more cells than there are items
+        if cell_count > self.items.len() as f64 {
+            return self.items.values().filter(inside).collect();
+        }
+
+        let mut items = Vec::new();
+        for cx in min_cx..=max_cx {
+            for cy in min_cy..=max_cy {
+                if let Some(ids) = self.cells.get(&(cx, cy)) {
+                    items.extend(ids.iter().filter_map(|id| self.items.get(id)).filter(inside));
+                }
+            }
+        }
+        items
+    }
+
+    // Items within `radius` of (x, y)
+    fn items_within(&self, x: f64, y: f64, radius: f64) -> Vec<&LevelItem> {
+        self.items_in_rect(x - radius, y - radius, x + radius, y + radius)
+            .into_iter()
+            .filter(|item| {
+                let (ix, iy) = item.xy();
+                (ix - x).powi(2) + (iy - y).powi(2) <= radius * radius
+            })
+            .collect()
+    }
+
+    // Items a cluster seeded by `seed` takes: the `available` ones within `radius` of its centroid that
+    // the topology lets it, in ascending id order
+    fn take(&self, input: &LevelInput, seed: &LevelItem, radius: f64, available: impl Fn(&LevelItem) -> bool) -> Vec<&LevelItem> {
+        let (x, y) = seed.xy();
+        let mut nearby: Vec<&LevelItem> = self.items_within(x, y, radius)
+            .into_iter()
+            .filter(|item| item.id != seed.id && available(item))
+            .collect();
+        nearby.sort_unstable_by_key(|item| item.id);
+        match input.topology {
+            Some(topology) => topology.select(input.vertices, seed, &nearby),
+            None => nearby,
+        }
+    }
+
+    // Greedy radius clustering like supercluster's, in ascending id order: each item not taken yet
+    // takes the other ones within `radius` of it. Returns the children of every item one zoom below.
+    fn cluster(&self, input: &LevelInput, radius: f64) -> Vec<Vec<usize>> {
+        let mut ids: Vec<usize> = self.items.keys().copied().collect();
+        ids.sort_unstable();
+
+        let mut taken = HashSet::new();
+        let mut groups = Vec::new();
+        for id in ids {
+            if !taken.insert(id) {
+                continue;
+            }
+            let nearby = self.take(input, &self.items[&id], radius, |item| !taken.contains(&item.id));
+            let mut children = vec![id];
+            for item in nearby {
+                taken.insert(item.id);
+                children.push(item.id);
+            }
+            groups.push(children);
+        }
+        groups
+    }
+
+    // Id of the item clustered from `children`: a lone child keeps its id, clusters are named after their first member
+    fn group_id(&self, children: &[usize], cluster_id: impl Fn(usize) -> usize) -> usize {
+        if children.len() == 1 {
+            return children[0];
+        }
+        let first = children.iter().map(|child| self.items[child].members[0]).min().unwrap();
+        cluster_id(first)
+    }
+
+    // Cluster this level again after the items of the zoom above listed in `changed` (with their old
+    // version, None for new ones) were added, changed or removed. Only the seeds whose takes can differ are
+    // visited, in ascending id order like `cluster`, so the result is what clustering everything again
+    // would give. Returns the items of this level that changed, in the same form.
+    fn recluster(&mut self, input: &LevelInput, above: &ClusterLevel, changed: &HashMap<usize, Option<LevelItem>>, radius: f64, cluster_id: impl Fn(usize) -> usize) -> HashMap<usize, Option<LevelItem>> {
+        let mut pass = Recluster { lower: self, above, changed, radius, takers: HashMap::new(), queue: BTreeSet::new() };
+        let (stale, fresh) = pass.run(input);
+
+        let mut updated = HashMap::new();
+        for id in stale {
+            if let Some(old) = self.remove_item(id) {
+                for member in &old.members {
+                    self.visible[*member] = NO_ITEM;
+                }
+                updated.insert(id, Some(old));
+            }
+        }
+        for children in fresh {
+            let id = above.group_id(&children, &cluster_id);
+            self.insert_group(id, children, above);
+            match updated.get(&id) {
+                Some(Some(old)) if *old == self.items[&id] => {
+                    updated.remove(&id);
+                }
+                Some(_) => {}
+                None => {
+                    updated.insert(id, None);
+                }
+            }
+        }
+        updated
+    }
+}
+
+// One `recluster` pass. `takers` holds the seed taking each item as decided by this pass, NO_ITEM while
+// undecided; items it has not decided keep the seed of the cluster they are in on the level below.
+struct Recluster<'a> {
+    lower: &'a ClusterLevel,
+    above: &'a ClusterLevel,
+    changed: &'a HashMap<usize, Option<LevelItem>>,
+    radius: f64,
+    takers: HashMap<usize, usize>,
+    queue: BTreeSet<usize>,
+}
+
+impl<'a> Recluster<'a> {
+    // Cluster holding an item of the zoom above before the change
+    fn old_parent(&self, id: usize) -> Option<&'a LevelItem> {
+        let first = match self.changed.get(&id) {
+            Some(Some(old)) => old.members[0],
+            Some(None) => return None,
+            None => self.above.items.get(&id)?.members[0],
+        };
+        let lower = self.lower;
+        lower.items.get(lower.visible.get(first)?)
+    }
+
+    fn taker(&self, id: usize) -> usize {
+        match self.takers.get(&id) {
+            Some(taker) => *taker,
+            None => self.old_parent(id).map_or(NO_ITEM, |parent| parent.children[0]),
+        }
+    }
+
+    // Seeds between the old and new taker of `id` see it change availability, so they are visited again
+    fn retake(&mut self, id: usize, taker: usize) {
+        let old = self.taker(id);
+        let item = match self.above.items.get(&id) {
+            Some(item) if old != taker => item,
+            _ => return,
+        };
+        self.takers.insert(id, taker);
+        if taker == NO_ITEM {
+            self.queue.insert(id);
+        }
+        let low = old.min(taker);
+        let (x, y) = item.xy();
+        let seeds: Vec<usize> = self.above.items_within(x, y, self.radius)
+            .into_iter()
+            .map(|item| item.id)
+            .filter(|other| *other > low && *other < id)
+            .collect();
+        self.queue.extend(seeds);
+    }
+
+    // Returns the ids of the clusters below to drop and the children of the ones to add
+    fn run(&mut self, input: &LevelInput) -> (HashSet<usize>, Vec<Vec<usize>>) {
+        let (above, changed) = (self.above, self.changed);
+        let mut stale = HashSet::new();
+        for (id, old) in changed {
+            let current = above.items.get(id);
+            for item in current.into_iter().chain(old.as_ref()) {
+                let (x, y) = item.xy();
+                let seeds = above.items_within(x, y, self.radius).into_iter().map(|other| other.id).filter(|other| other < id);
+                self.queue.extend(seeds);
+            }
+            if current.is_some() {
+                self.queue.insert(*id);
+            }
+            if let Some(parent) = self.old_parent(*id) {
+                stale.insert(parent.id);
+                let children = parent.children.iter().filter(|child| above.items.contains_key(child));
+                self.queue.extend(children);
+                // a removed seed leaves what it took undecided
+                if current.is_none() && parent.children[0] == *id {
+                    for child in &parent.children[1..] {
+                        self.retake(*child, NO_ITEM);
+                    }
+                }
+            }
+        }
+
+        let mut fresh = Vec::new();
+        while let Some(seed) = self.queue.pop_first() {
+            let item = match above.items.get(&seed) {
+                Some(item) => item,
+                None => continue,
+            };
+            let old_parent = self.old_parent(seed);
+            let old_children: &[usize] = match old_parent {
+                Some(parent) if parent.children[0] == seed => &parent.children,
+                _ => &[],
+            };
+
+            let taker = self.taker(seed);
+            if taker != NO_ITEM && taker < seed {
+                // taken by an earlier seed, whatever it took before is free again
+                if !old_children.is_empty() {
+                    stale.extend(old_parent.map(|parent| parent.id));
+                }
+                for child in old_children.iter().skip(1) {
+                    if self.taker(*child) == seed {
+                        self.retake(*child, NO_ITEM);
+                    }
+                }
+                continue;
+            }
+
+            self.retake(seed, seed);
+            let nearby: Vec<usize> = above.take(input, item, self.radius, |other| self.taker(other.id) >= seed)
+                .into_iter()
+                .map(|other| other.id)
+                .collect();
+            for id in &nearby {
+                // the seed it leaves loses it, or it was a seed itself and gives up its cluster
+                let previous = self.taker(*id);
+                if previous != NO_ITEM && previous > seed {
+                    self.queue.insert(previous);
+                }
+                self.retake(*id, seed);
+            }
+            for child in old_children.iter().skip(1) {
+                if !nearby.contains(child) && self.taker(*child) == seed {
+                    self.retake(*child, NO_ITEM);
+                }
+            }
+
+            let mut children = vec![seed];
+            children.extend(nearby);
+            if children != old_children || children.iter().any(|child| changed.contains_key(child)) {
+                stale.extend(old_parent.map(|parent| parent.id));
+                fresh.push(children);
+            }
+        }
+        (stale, fresh)
+    }
+}
+
+// What the clustering reads about the sources
+pub struct LevelInput<'a> {
+    pub points: &'a [(f64, f64)],
+    pub weights: &'a [f64],
+    // graph vertex index per source, read by the topology
+    pub vertices: &'a [usize],
+    pub topology: Option<&'a Topology>,
+    // removed sources keep their slot until the next rebuild
+    pub live: &'a dyn Fn(usize) -> bool,
+}
+
+// Every zoom of the index, clustered top-down from level `max_zoom + 1`, which holds the unclustered points.
+// Cluster ids only depend on the members, so patching the levels gives what a rebuild would.
+#[derive(Serialize, Deserialize)]
+pub struct ClusterLevels {
+    pub min_zoom: usize,
+    pub max_zoom: usize,
+    pub radius: f64,
+    // ids below this are source points, cluster ids start at it
+    pub source_capacity: usize,
+    pub levels: Vec<ClusterLevel>,
+}
+
+impl ClusterLevels {
+    // Cluster radius at `zoom` in normalized units
+    pub fn zoom_radius(&self, zoom: usize) -> f64 {
+        self.radius / (TILE_SIZE * 2f64.powi(zoom as i32))
+    }
+
+    fn cell_size(&self, zoom: usize) -> f64 {
+        self.zoom_radius(zoom).max(1e-9)
+    }
+
+    fn empty(min_zoom: usize, max_zoom: usize, radius: f64, source_capacity: usize, sources: usize) -> Self {
+        let mut levels = Self { min_zoom, max_zoom, radius, source_capacity, levels: Vec::new() };
+        for zoom in min_zoom..=max_zoom + 1 {
+            let cell_size = levels.cell_size(zoom);
+            levels.levels.push(ClusterLevel::new(zoom, cell_size, sources));
+        }
+        levels
+    }
+
+    // Rebuild items and grids of deserialized levels from the item id of every source, from the points down
+    pub fn restore(&mut self, points: &[(f64, f64)], weights: &[f64]) {
+        let top = self.levels.len() - 1;
+        for index in (0..=top).rev() {
+            let cell_size = self.cell_size(self.levels[index].zoom);
+            let (lower, upper) = self.levels.split_at_mut(index + 1);
+            let level = &mut lower[index];
+            level.cell_size = cell_size;
+            level.cells.clear();
+            level.items.clear();
+
+            if index == top {
+                for source in 0..level.visible.len() {
+                    if level.visible[source] != NO_ITEM {
+                        level.insert_item(source, vec![source], points, weights);
+                    }
+                }
+                continue;
+            }
+            let above = &upper[0];
+            let mut children: HashMap<usize, Vec<usize>> = HashMap::new();
+            for (source, id) in level.visible.iter().enumerate() {
+                if *id != NO_ITEM {
+                    children.entry(*id).or_default().push(above.visible[source]);
+                }
+            }
+            for (id, mut children) in children {
+                children.sort_unstable();
+                children.dedup();
+                level.insert_group(id, children, above);
+            }
+        }
+    }
+
+    // Cluster the live sources at every zoom, from the points down. Cluster ids start at `source_capacity`,
+    // leaving room for sources inserted before the next rebuild.
+    pub fn build(input: &LevelInput, min_zoom: usize, max_zoom: usize, radius: f64, source_capacity: usize) -> Self {
+        let mut levels = Self::empty(min_zoom, max_zoom, radius, source_capacity, input.points.len());
+        let top = levels.levels.len() - 1;
+        for source in (0..input.points.len()).filter(|source| (input.live)(*source)) {
+            levels.levels[top].insert_item(source, vec![source], input.points, input.weights);
+        }
+
+        for index in (0..top).rev() {
+            let zoom = levels.levels[index].zoom;
+            let radius = levels.zoom_radius(zoom);
+            let (lower, upper) = levels.levels.split_at_mut(index + 1);
+            for children in upper[0].cluster(input, radius) {
+                let id = upper[0].group_id(&children, |first| cluster_id(source_capacity, first, zoom));
+                lower[index].insert_group(id, children, &upper[0]);
+            }
+        }
+
+        levels
+    }
+
+    pub fn is_cluster(&self, id: usize) -> bool {
+        id >= self.source_capacity
+    }
+
+    pub fn level(&self, zoom: usize) -> &ClusterLevel {
+        let zoom = zoom.clamp(self.min_zoom, self.max_zoom + 1);
+        &self.levels[zoom - self.min_zoom]
+    }
+
+    // Highest zoom at which `id` is visible, a cluster splits into its children one zoom above
+    pub fn last_zoom(&self, id: usize) -> Option<usize> {
+        self.levels.iter().rev()
+            .find(|level| level.items.contains_key(&id))
+            .map(|level| level.zoom)
+    }
+
+    pub fn find_item(&self, id: usize) -> Option<&LevelItem> {
+        self.levels.iter().rev().find_map(|level| level.items.get(&id))
+    }
+
+    // Items one zoom below `id` in the tree, i.e. what it splits into, in the order of their first member
+    pub fn children(&self, id: usize) -> Vec<&LevelItem> {
+        let (item, zoom) = match (self.find_item(id), self.last_zoom(id)) {
+            (Some(item), Some(zoom)) if zoom <= self.max_zoom => (item, zoom),
+            _ => return Vec::new(),
+        };
+        let level = self.level(zoom + 1);
+        let mut children: Vec<&LevelItem> = item.children.iter().filter_map(|child| level.items.get(child)).collect();
+        children.sort_unstable_by_key(|child| child.members[0]);
+        children
+    }
+
+    // Zoom at which the cluster breaks apart, following single-child chains like supercluster
+    pub fn expansion_zoom(&self, id: usize) -> Option<usize> {
+        let mut zoom = self.last_zoom(id)?;
+        let mut id = id;
+        while zoom <= self.max_zoom {
+            let children = self.children(id);
+            zoom += 1;
+            if children.len() != 1 {
+                break;
+            }
+            id = children[0].id;
+        }
+        Some(zoom)
+    }
+
+    // Bbox query, split at the antimeridian
+    pub fn items_in_bbox(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> Vec<&LevelItem> {
+        let level = self.level(zoom);
+        normalize_bbox(min_lng, min_lat, max_lng, max_lat)
+            .into_iter()
+            .flat_map(|b| level.items_in_rect(lng_x(b[0]), lat_y(b[3]), lng_x(b[2]), lat_y(b[1])))
+            .collect()
+    }
+
+    // Items of one zoom inside a normalized rectangle, without antimeridian handling
+    pub fn items_in_rect(&self, zoom: usize, min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Vec<&LevelItem> {
+        self.level(zoom).items_in_rect(min_x, min_y, max_x, max_y)
+    }
+
+    // Ids of every item containing `source`, at any zoom
+    pub fn chain(&self, source: usize) -> Vec<usize> {
+        let mut ids: Vec<usize> = self.levels.iter()
+            .filter_map(|level| level.visible.get(source).copied())
+            .filter(|id| *id != NO_ITEM)
+            .collect();
+        ids.dedup();
+        ids
+    }
+
+    // Bring the levels in line with inserted, removed, moved or reweighted sources. At each zoom, top-down,
+    // the level is clustered again around the items of the zoom above that changed, see `recluster`, so
+    // this ends where a rebuild would at a cost that follows the changes. Returns the ids that changed.
+    pub fn update_sources(&mut self, input: &LevelInput, changed: &[usize]) -> Vec<usize> {
+        let sources = input.points.len();
+        for level in self.levels.iter_mut() {
+            if level.visible.len() < sources {
+                level.visible.resize(sources, NO_ITEM);
+            }
+        }
+
+        let top = self.levels.len() - 1;
+        let points = &mut self.levels[top];
+        let mut updated: HashMap<usize, Option<LevelItem>> = HashMap::new();
+        for source in changed {
+            let old = points.remove_item(*source);
+            points.visible[*source] = NO_ITEM;
+            if (input.live)(*source) {
+                points.insert_item(*source, vec![*source], input.points, input.weights);
+            }
+            if old.as_ref() != points.items.get(source) {
+                updated.entry(*source).or_insert(old);
+            }
+        }
+
+        let source_capacity = self.source_capacity;
+        let mut ids: Vec<usize> = updated.keys().copied().collect();
+        for index in (0..top).rev() {
+            if updated.is_empty() {
+                break;
+            }
+            let zoom = self.levels[index].zoom;
+            let radius = self.zoom_radius(zoom);
+            let (lower, upper) = self.levels.split_at_mut(index + 1);
+            updated = lower[index].recluster(input, &upper[0], &updated, radius, |first| cluster_id(source_capacity, first, zoom));
+            ids.extend(updated.keys());
+        }
+
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+}
+
+// Id of a cluster formed at `zoom` whose lowest member is `first`
+fn cluster_id(source_capacity: usize, first: usize, zoom: usize) -> usize {
+    source_capacity + (first << 5) + zoom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supercluster::fixtures::{assert_same_levels, scattered_points};
+
+    #[test]
+    fn build_clusters_every_zoom() {
+        let points = scattered_points(200, 1);
+        let weights = vec![1.0; points.len()];
+        let vertices: Vec<usize> = (0..points.len()).collect();
+        let input = LevelInput { points: &points, weights: &weights, vertices: &vertices, topology: None, live: &|_| true };
+        let levels = ClusterLevels::build(&input, 0, 16, 40.0, 300);
+
+        let mut previous = usize::MAX;
+        for level in &levels.levels {
+            // every source in exactly one item, items never closer than the radius to a seed they were not merged into
+            let mut members: Vec<usize> = level.items.values().flat_map(|item| item.members.iter().copied()).collect();
+            members.sort_unstable();
+            assert_eq!(members, (0..points.len()).collect::<Vec<usize>>());
+            for item in level.items.values() {
+                assert!(item.members.iter().all(|member| level.visible[*member] == item.id));
+                assert_eq!(levels.is_cluster(item.id), item.count() > 1);
+            }
+            assert!(level.items.len() >= previous || previous == usize::MAX);
+            previous = level.items.len();
+        }
+        assert!(levels.level(0).items.len() < 16);
+        assert_eq!(levels.level(17).items.len(), points.len());
+
+        // A cluster splits into its children one zoom up, until single points
+        let cluster = levels.level(3).items.values().find(|item| item.count() > 1).unwrap();
+        let children = levels.children(cluster.id);
+        assert_eq!(children.iter().map(|child| child.count()).sum::<usize>(), cluster.count());
+        assert!(levels.expansion_zoom(cluster.id).unwrap() > levels.last_zoom(cluster.id).unwrap());
+        assert!(levels.children(0).is_empty());
+    }
+
+    #[test]
+    fn updates_match_a_rebuild() {
+        let mut points = scattered_points(150, 7);
+        let mut weights: Vec<f64> = (0..points.len()).map(|source| (source % 5) as f64).collect();
+        let vertices: Vec<usize> = (0..400).collect();
+        let mut live = vec![true; points.len()];
+        let source_capacity = 400;
+
+        let levels_of = |points: &[(f64, f64)], weights: &[f64], live: &[bool]| {
+            let is_live = |source: usize| live[source];
+            let input = LevelInput { points, weights, vertices: &vertices, topology: None, live: &is_live };
+            ClusterLevels::build(&input, 0, 14, 60.0, source_capacity)
+        };
+        let mut levels = levels_of(&points, &weights, &live);
+
+        let extra = scattered_points(40, 99);
+        for step in 0..120 {
+            let source = match step % 4 {
+                // insert
+                0 => {
+                    points.push(extra[step / 4 % extra.len()]);
+                    weights.push(1.0);
+                    live.push(true);
+                    points.len() - 1
+                }
+                // remove
+                1 => {
+                    let source = (step * 37) % points.len();
+                    live[source] = false;
+                    source
+                }
+                // move
+                2 => {
+                    let source = (step * 53) % points.len();
+                    let (lng, lat) = extra[(step * 3) % extra.len()];
+                    points[source] = (lng + 0.01, lat - 0.01);
+                    source
+                }
+                // reweight
+                _ => {
+                    let source = (step * 11) % points.len();
+                    weights[source] = (step % 7) as f64;
+                    source
+                }
+            };
+
+            let is_live = |source: usize| live[source];
+            let input = LevelInput { points: &points, weights: &weights, vertices: &vertices, topology: None, live: &is_live };
+            let updated = levels.update_sources(&input, &[source]);
+            let rebuilt = levels_of(&points, &weights, &live);
+            assert_same_levels(&levels, &rebuilt);
+            if step % 4 != 3 {
+                assert!(!updated.is_empty() || !live[source]);
+            }
+        }
+    }
+
+    #[test]
+    fn updates_cost_less_than_a_rebuild() {
+        // 100k points, a few thousand per square degree, refreshed with a handful of moves
+        let mut points = scattered_points(100_000, 3);
+        let weights = vec![1.0; points.len()];
+        let vertices: Vec<usize> = (0..points.len()).collect();
+        let input = LevelInput { points: &points, weights: &weights, vertices: &vertices, topology: None, live: &|_| true };
+        let started = std::time::Instant::now();
+        let mut levels = ClusterLevels::build(&input, 0, 16, 40.0, 200_000);
+        let rebuild = started.elapsed();
+
+        let moved: Vec<usize> = (0..10).map(|step| step * 9_973).collect();
+        for source in &moved {
+            let (lng, lat) = points[*source];
+            points[*source] = (lng + 0.05, lat - 0.05);
+        }
+        let input = LevelInput { points: &points, weights: &weights, vertices: &vertices, topology: None, live: &|_| true };
+        let started = std::time::Instant::now();
+        levels.update_sources(&input, &moved);
+        let update = started.elapsed();
+
+        assert_same_levels(&levels, &ClusterLevels::build(&input, 0, 16, 40.0, 200_000));
+        assert!(update * 20 < rebuild, "update {:?}, rebuild {:?}", update, rebuild);
+    }
+}
@@ -0,0 +1,296 @@
+// meta edges
+
+use std::collections::{HashMap, HashSet};
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use serde_wasm_bindgen::to_value;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::supercluster::SuperclusterWrapper;
+use crate::supercluster::accumulators::{AccumulatorDeclaration, AccumulatorKind};
+use crate::supercluster::levels::NO_ITEM;
+
+// Relations aggregated between two visible clusters / points
+#[derive(Serialize)]
+pub struct ClusterEdge {
+    pub source: usize,
+    pub target: usize,
+    pub source_position: Vec<f64>,
+    pub target_position: Vec<f64>,
+    pub count: usize,
+    pub weight: f64,
+    pub worst_thr_id: Option<i32>,
+}
+
+// Relations whose both ends fall inside the same cluster
+#[derive(Serialize)]
+pub struct InternalLinks {
+    pub id: usize,
+    pub count: usize,
+    pub weight: f64,
+    pub worst_thr_id: Option<i32>,
+}
+
+#[derive(Serialize)]
+pub struct ClusterEdgesResult {
+    pub edges: Vec<ClusterEdge>,
+    pub internal: Vec<InternalLinks>,
+}
+
+// A cluster or point visible at the queried zoom with relations to the members of another cluster
+#[derive(Serialize)]
+pub struct ClusterNeighbor {
+    pub id: usize,
+    pub cluster: bool,
+    pub position: Vec<f64>,
+    pub point_count: usize,
+    // relations between its members and the selected cluster's members
+    pub count: usize,
+    pub weight: f64,
+    pub worst_thr_id: Option<i32>,
+}
+
+// The more severe of two threshold ids on the scale of `severity`, the higher id without one
+pub fn worse_thr_id(severity: Option<&AccumulatorDeclaration>, current: Option<i32>, other: Option<i32>) -> Option<i32> {
+    let rank = |thr_id: i32| severity.map_or((false, thr_id as i64), |declaration| declaration.severity_rank(thr_id as i64));
+    match (current, other) {
+        (Some(a), Some(b)) => Some(if rank(b) > rank(a) { b } else { a }),
+        (a, b) => a.or(b),
+    }
+}
+
+impl SuperclusterWrapper {
+    // The first declared `severity` accumulator, whose scale ranks the threshold ids of relations
+    pub fn severity_scale(&self) -> Option<&AccumulatorDeclaration> {
+        self.accumulators.iter().find(|declaration| declaration.kind == AccumulatorKind::Severity)
+    }
+
+    pub fn cluster_edges(&self, graph: &GraphWrapper, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> ClusterEdgesResult {
+        let level = self.levels.level(zoom);
+        let in_view: HashSet<usize> = self.clusters_in_bbox(min_lng, min_lat, max_lng, max_lat, zoom)
+            .iter()
+            .map(|item| item.id)
+            .collect();
+
+        let severity = self.severity_scale();
+        let mut edges: HashMap<(usize, usize), ClusterEdge> = HashMap::new();
+        let mut internal: HashMap<usize, InternalLinks> = HashMap::new();
+
+        for (from, to, relation) in graph.load_relations() {
+            let visible = |vertex: usize| {
+                self.sources.get(&vertex)
+                    .and_then(|source| level.visible.get(*source))
+                    .copied()
+                    .filter(|id| *id != NO_ITEM)
+            };
+            let (source, target) = match (visible(from), visible(to)) {
+                (Some(source), Some(target)) => (source, target),
+                _ => continue,
+            };
+            // Only edges with at least one end in the viewport
+            if !in_view.contains(&source) && !in_view.contains(&target) {
+                continue;
+            }
+
+            if source == target {
+                let links = internal.entry(source).or_insert(InternalLinks { id: source, count: 0, weight: 0.0, worst_thr_id: None });
+                links.count += 1;
+                links.weight += relation.weight;
+                links.worst_thr_id = worse_thr_id(severity, links.worst_thr_id, relation.thr_id);
+                continue;
+            }
+
+            let key = (source.min(target), source.max(target));
+            let edge = edges.entry(key).or_insert_with(|| {
+                let position = |id: &usize| {
+                    level.items.get(id)
+                        .map(|item| {
+                            let (x, y) = item.lng_lat();
+                            vec![x, y]
+                        })
+                        .unwrap_or_default()
+                };
+                ClusterEdge {
+                    source: key.0,
+                    target: key.1,
+                    source_position: position(&key.0),
+                    target_position: position(&key.1),
+                    count: 0,
+                    weight: 0.0,
+                    worst_thr_id: None,
+                }
+            });
+            edge.count += 1;
+            edge.weight += relation.weight;
+            edge.worst_thr_id = worse_thr_id(severity, edge.worst_thr_id, relation.thr_id);
+        }
+
+        let mut edges: Vec<ClusterEdge> = edges.into_values().collect();
+        edges.sort_by_key(|edge| (edge.source, edge.target));
+        let mut internal: Vec<InternalLinks> = internal.into_values().collect();
+        internal.sort_by_key(|links| links.id);
+
+        ClusterEdgesResult { edges, internal }
+    }
+
+    // Clusters and points at `zoom` sharing relations with the members of `cluster_id`, most connected first
+    pub fn cluster_neighbors(&self, graph: &GraphWrapper, cluster_id: usize, zoom: usize) -> Result<Vec<ClusterNeighbor>, String> {
+        let members: HashSet<usize> = match self.levels.find_item(cluster_id) {
+            Some(item) => item.members.iter().copied().collect(),
+            None => return Err(format!("No cluster with the specified id {}", cluster_id)),
+        };
+        let level = self.levels.level(zoom);
+        // Below the cluster's own zooms its members sit in larger clusters, which are not neighbors either
+        let own: HashSet<usize> = members.iter().filter_map(|member| level.visible.get(*member).copied()).collect();
+        let source_of = |vertex: usize| self.sources.get(&vertex).copied();
+        let severity = self.severity_scale();
+
+        let mut neighbors: HashMap<usize, ClusterNeighbor> = HashMap::new();
+        for (from, to, relation) in graph.load_relations() {
+            let other = match (source_of(from), source_of(to)) {
+                (Some(from), Some(to)) if members.contains(&from) && !members.contains(&to) => to,
+                (Some(from), Some(to)) if members.contains(&to) && !members.contains(&from) => from,
+                _ => continue,
+            };
+            let item = match level.visible.get(other).filter(|id| !own.contains(id)).and_then(|id| level.items.get(id)) {
+                Some(item) => item,
+                None => continue,
+            };
+
+            let neighbor = neighbors.entry(item.id).or_insert_with(|| {
+                let (x, y) = item.lng_lat();
+                ClusterNeighbor {
+                    id: item.id,
+                    cluster: self.levels.is_cluster(item.id),
+                    position: vec![x, y],
+                    point_count: item.count(),
+                    count: 0,
+                    weight: 0.0,
+                    worst_thr_id: None,
+                }
+            });
+            neighbor.count += 1;
+            neighbor.weight += relation.weight;
+            neighbor.worst_thr_id = worse_thr_id(severity, neighbor.worst_thr_id, relation.thr_id);
+        }
+
+        let mut neighbors: Vec<ClusterNeighbor> = neighbors.into_values().collect();
+        neighbors.sort_by_key(|neighbor| (std::cmp::Reverse(neighbor.count), neighbor.id));
+        Ok(neighbors)
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // Graph relations rolled up to the clusters and points visible at `zoom`
+    #[wasm_bindgen]
+    pub fn get_cluster_edges(&self, graph: &GraphWrapper, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> JsValue {
+        to_value(&self.cluster_edges(graph, min_lng, min_lat, max_lng, max_lat, zoom)).unwrap()
+    }
+
+    // What a selected cluster talks to: the clusters and points at `zoom` directly related to its
+    // members, with the number of relations and their worst `thr_id`. Neither the cluster's members
+    // nor, below the zooms it is visible at, the clusters containing it are neighbors.
+    #[wasm_bindgen]
+    pub fn get_cluster_neighbors(&self, graph: &GraphWrapper, cluster_id: usize, zoom: usize) -> Result<JsValue, JsValue> {
+        match self.cluster_neighbors(graph, cluster_id, zoom) {
+            Ok(neighbors) => Ok(to_value(&neighbors).unwrap()),
+            Err(e) => Err(JsValue::from_str(&e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn worse_thr_id_follows_the_severity_scale() {
+        use crate::supercluster::accumulators::SeverityLevel;
+
+        assert_eq!(worse_thr_id(None, Some(1), Some(3)), Some(3));
+        assert_eq!(worse_thr_id(None, None, Some(2)), Some(2));
+        assert_eq!(worse_thr_id(None, Some(2), None), Some(2));
+
+        // 3 is the least severe, 1 the most, ids off the scale below both
+        let mut declaration = AccumulatorDeclaration::threshold_counter();
+        declaration.kind = AccumulatorKind::Severity;
+        declaration.severity = [3, 2, 1].iter().map(|thr_id| SeverityLevel { thr_id: *thr_id, label: None }).collect();
+        assert_eq!(worse_thr_id(Some(&declaration), Some(1), Some(3)), Some(1));
+        assert_eq!(worse_thr_id(Some(&declaration), Some(3), Some(2)), Some(2));
+        assert_eq!(worse_thr_id(Some(&declaration), Some(7), Some(3)), Some(3));
+        assert_eq!(worse_thr_id(Some(&declaration), Some(7), Some(9)), Some(9));
+    }
+
+    /// An edge as (ends, count, weight, worst thr_id)
+    type EdgeSummary = ((usize, usize), usize, f64, Option<i32>);
+
+    fn related_clusters(accumulators: Vec<AccumulatorDeclaration>) -> (GraphWrapper, SuperclusterWrapper) {
+        use crate::supercluster::fixtures::{graph_of, index_of};
+        use crate::supercluster::supercluster::ClusterOptions;
+
+        // Two clusters at zoom 0, a three point one around 0° and a two point one at 60°, and a lone point
+        let points = [(0.0, 0.0), (0.01, 0.0), (0.0, 0.01), (60.0, 0.0), (60.01, 0.0), (-100.0, 40.0)];
+        let mut graph = graph_of(points.map(|point| (point, None)));
+        for (id, (from, to, weight, thr_id)) in vec![(0, 1, 1.0, 1), (0, 3, 2.0, 1), (1, 4, 3.0, 3), (2, 5, 1.0, 2), (3, 4, 1.0, 2)].into_iter().enumerate() {
+            graph.create_relation(id, from, to, "fiber".to_string(), weight, Some(thr_id)).unwrap();
+        }
+        let index = index_of(&graph, ClusterOptions { accumulators, ..Default::default() });
+        (graph, index)
+    }
+
+    #[test]
+    fn cluster_edges_roll_relations_up_to_the_visible_clusters() {
+        use crate::supercluster::accumulators::SeverityLevel;
+
+        let (graph, index) = related_clusters(vec![AccumulatorDeclaration::threshold_counter()]);
+        let visible = |vertex: usize, zoom: usize| index.levels.level(zoom).visible[index.sources[&vertex]];
+        let (a, b, lone) = (visible(0, 0), visible(3, 0), visible(5, 0));
+        assert!(index.levels.is_cluster(a) && index.levels.is_cluster(b) && !index.levels.is_cluster(lone));
+
+        let result = index.cluster_edges(&graph, -180.0, -85.0, 180.0, 85.0, 0);
+        let summary: Vec<EdgeSummary> = result.edges.iter()
+            .map(|edge| ((edge.source, edge.target), edge.count, edge.weight, edge.worst_thr_id))
+            .collect();
+        let mut expected = vec![((a.min(b), a.max(b)), 2, 5.0, Some(3)), ((a.min(lone), a.max(lone)), 1, 1.0, Some(2))];
+        expected.sort_by_key(|edge| edge.0);
+        assert_eq!(summary, expected);
+        let internal: Vec<(usize, usize)> = result.internal.iter().map(|links| (links.id, links.count)).collect();
+        let mut expected = vec![(a, 1), (b, 1)];
+        expected.sort_unstable();
+        assert_eq!(internal, expected);
+
+        // Only relations with an end in view, and every relation between points once they are apart
+        assert!(index.cluster_edges(&graph, -120.0, 30.0, -90.0, 50.0, 0).edges.iter().all(|edge| edge.source == lone || edge.target == lone));
+        let result = index.cluster_edges(&graph, -180.0, -85.0, 180.0, 85.0, 16);
+        assert_eq!(result.edges.len(), 5);
+        assert!(result.internal.is_empty());
+
+        // A severity scale ranks 1 above 3
+        let mut severity = AccumulatorDeclaration::threshold_counter();
+        severity.kind = AccumulatorKind::Severity;
+        severity.severity = [3, 2, 1].iter().map(|thr_id| SeverityLevel { thr_id: *thr_id, label: None }).collect();
+        let (graph, index) = related_clusters(vec![severity]);
+        let result = index.cluster_edges(&graph, -180.0, -85.0, 180.0, 85.0, 0);
+        assert_eq!(result.edges.iter().find(|edge| edge.count == 2).unwrap().worst_thr_id, Some(1));
+    }
+
+    #[test]
+    fn neighbors_skip_the_clusters_holding_the_selection() {
+        let (graph, index) = related_clusters(vec![AccumulatorDeclaration::threshold_counter()]);
+        let visible = |vertex: usize, zoom: usize| index.levels.level(zoom).visible[index.sources[&vertex]];
+        let (a, b, lone) = (visible(0, 0), visible(3, 0), visible(5, 0));
+
+        let neighbors = index.cluster_neighbors(&graph, a, 0).unwrap();
+        let summary: Vec<(usize, bool, usize, usize)> = neighbors.iter().map(|neighbor| (neighbor.id, neighbor.cluster, neighbor.point_count, neighbor.count)).collect();
+        assert_eq!(summary, vec![(b, true, 2, 2), (lone, false, 1, 1)]);
+
+        // Point 0 sits in `a` at zoom 0, so its relation to point 1 has no neighbor there
+        let point = index.sources[&0];
+        let neighbors: Vec<usize> = index.cluster_neighbors(&graph, point, 0).unwrap().iter().map(|neighbor| neighbor.id).collect();
+        assert_eq!(neighbors, vec![b]);
+        let neighbors: Vec<usize> = index.cluster_neighbors(&graph, point, 16).unwrap().iter().map(|neighbor| neighbor.id).collect();
+        assert_eq!(neighbors, vec![index.sources[&1], index.sources[&3]]);
+
+        assert!(index.cluster_neighbors(&graph, 1 << 40, 0).is_err());
+    }
+}
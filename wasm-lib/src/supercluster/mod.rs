@@ -1,4 +1,21 @@
+#[allow(clippy::module_inception)]
 pub mod supercluster;
+pub mod accumulators;
+pub mod levels;
+pub mod meta_edges;
+pub mod updates;
+pub mod filters;
+pub mod indexes;
+pub mod features;
+pub mod vector_tiles;
+pub mod outlines;
+pub mod spiderfy;
+pub mod hierarchy;
+pub mod snapshot;
+pub mod topology;
+pub mod timeline;
+pub mod labels;
+#[cfg(test)]
+pub(crate) mod fixtures;
 
 pub use supercluster::{SuperclusterWrapper};
-
@@ -0,0 +1,356 @@
+// outlines
+
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_wasm_bindgen::{from_value, to_value};
+use geo::{Area, BooleanOps, ConcaveHull, Contains, ConvexHull, EuclideanDistance, TriangulateSpade};
+use geo_types::{Coord, LineString, MultiPoint, MultiPolygon, Point, Polygon};
+use crate::supercluster::SuperclusterWrapper;
+use crate::projection::web_mercator::{from_meters, to_meters};
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum OutlineKind {
+    Convex,
+    Concave,
+    Alpha,
+}
+
+// How `get_cluster_info` outlines the leaves of a cluster. Distances are ground meters.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OutlineOptions {
+    #[serde(default = "default_kind")]
+    pub kind: OutlineKind,
+    // geo's concave hull parameter, lower is tighter
+    #[serde(default = "default_concavity")]
+    pub concavity: f64,
+    // Largest circumradius of a kept triangle, 0 picks twice the median triangulation edge
+    #[serde(default)]
+    pub alpha: f64,
+    #[serde(default)]
+    pub buffer: f64,
+    // Radius of the circle / capsule drawn around one point or points on a line when `buffer` is 0
+    #[serde(default = "default_degenerate_radius")]
+    pub degenerate_radius: f64,
+    // Vertices per full circle of round caps
+    #[serde(default = "default_segments")]
+    pub segments: usize,
+}
+
+fn default_kind() -> OutlineKind {
+    OutlineKind::Convex
+}
+
+fn default_concavity() -> f64 {
+    2.0
+}
+
+fn default_degenerate_radius() -> f64 {
+    100.0
+}
+
+fn default_segments() -> usize {
+    24
+}
+
+impl Default for OutlineOptions {
+    fn default() -> Self {
+        Self {
+            kind: default_kind(),
+            concavity: default_concavity(),
+            alpha: 0.0,
+            buffer: 0.0,
+            degenerate_radius: default_degenerate_radius(),
+            segments: default_segments(),
+        }
+    }
+}
+
+// Polygons as GeoJSON MultiPolygon coordinates in lon / lat
+#[derive(Serialize, Clone)]
+pub struct Outline {
+    pub kind: OutlineKind,
+    // true when the leaves were a single point or on a line and a circle / capsule was drawn instead
+    pub degenerate: bool,
+    pub polygons: Vec<Vec<Vec<[f64; 2]>>>,
+}
+
+// Web Mercator meters stretch by 1 / cos(lat), scale ground distances by it around the leaves
+fn mercator_scale(lat: f64) -> f64 {
+    1.0 / (lat.to_radians().cos().max(1e-6))
+}
+
+fn circle(center: Coord<f64>, radius: f64, segments: usize) -> Vec<Coord<f64>> {
+    (0..segments)
+        .map(|i| {
+            let angle = 2.0 * PI * i as f64 / segments as f64;
+            Coord { x: center.x + radius * angle.cos(), y: center.y + radius * angle.sin() }
+        })
+        .collect()
+}
+
+// Convex hull of circles around every coordinate: an exact buffer of their convex hull
+fn rounded_hull(coords: &[Coord<f64>], radius: f64, segments: usize) -> Polygon<f64> {
+    let points: Vec<Point<f64>> = coords.iter()
+        .flat_map(|coord| circle(*coord, radius, segments))
+        .map(Point::from)
+        .collect();
+    MultiPoint::from(points).convex_hull()
+}
+
+// Delaunay triangles whose circumradius is at most `alpha`, traced back into polygons with holes
+fn alpha_shape(coords: &[Coord<f64>], alpha: f64) -> MultiPolygon<f64> {
+    let triangles = match LineString::from(coords.to_vec()).unconstrained_triangulation() {
+        Ok(triangles) => triangles,
+        Err(_) => return MultiPolygon::new(Vec::new()),
+    };
+
+    let alpha = if alpha > 0.0 {
+        alpha
+    } else {
+        let mut lengths: Vec<f64> = triangles.iter()
+            .flat_map(|triangle| triangle.to_lines().to_vec())
+            .map(|line| Point::from(line.start).euclidean_distance(&Point::from(line.end)))
+            .collect();
+        lengths.sort_by(f64::total_cmp);
+        2.0 * lengths.get(lengths.len() / 2).copied().unwrap_or(0.0)
+    };
+
+    // Directed edges of the kept triangles, all counter-clockwise
+    let key = |coord: Coord<f64>| (coord.x.to_bits(), coord.y.to_bits());
+    let mut edges: HashSet<((u64, u64), (u64, u64))> = HashSet::new();
+    let mut positions: HashMap<(u64, u64), Coord<f64>> = HashMap::new();
+    for triangle in &triangles {
+        let [a, b, c] = triangle.to_array();
+        let (ab, bc, ca) = (
+            Point::from(a).euclidean_distance(&Point::from(b)),
+            Point::from(b).euclidean_distance(&Point::from(c)),
+            Point::from(c).euclidean_distance(&Point::from(a)),
+        );
+        let area = triangle.unsigned_area();
+        if area <= 0.0 || ab * bc * ca / (4.0 * area) > alpha {
+            continue;
+        }
+        let (b, c) = if triangle.signed_area() > 0.0 { (b, c) } else { (c, b) };
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            positions.insert(key(from), from);
+            positions.insert(key(to), to);
+            edges.insert((key(from), key(to)));
+        }
+    }
+
+    // Boundary edges have no twin, chain them into rings
+    let mut next: HashMap<(u64, u64), Vec<(u64, u64)>> = HashMap::new();
+    for (from, to) in &edges {
+        if !edges.contains(&(*to, *from)) {
+            next.entry(*from).or_default().push(*to);
+        }
+    }
+
+    let mut rings: Vec<LineString<f64>> = Vec::new();
+    while let Some(start) = next.keys().next().copied() {
+        let mut ring = vec![positions[&start]];
+        let mut current = start;
+        while let Some(to) = next.get_mut(&current).and_then(|targets| targets.pop()) {
+            if next.get(&current).is_some_and(|targets| targets.is_empty()) {
+                next.remove(&current);
+            }
+            ring.push(positions[&to]);
+            current = to;
+            if current == start {
+                break;
+            }
+        }
+        if ring.len() >= 4 {
+            rings.push(LineString::from(ring));
+        }
+    }
+
+    // Counter-clockwise rings are shells, clockwise ones holes of the shell containing them
+    let (shells, holes): (Vec<LineString<f64>>, Vec<LineString<f64>>) = rings.into_iter()
+        .partition(|ring| Polygon::new(ring.clone(), Vec::new()).signed_area() > 0.0);
+    let mut polygons: Vec<Polygon<f64>> = shells.into_iter().map(|shell| Polygon::new(shell, Vec::new())).collect();
+    for hole in holes {
+        let inside = polygons.iter().position(|polygon| polygon.contains(&Point::from(hole.0[0])));
+        if let Some(index) = inside {
+            polygons[index].interiors_push(hole);
+        }
+    }
+
+    MultiPolygon::new(polygons)
+}
+
+// Union of the shapes with a capsule around every ring edge
+fn buffer_polygons(shapes: MultiPolygon<f64>, distance: f64, segments: usize) -> MultiPolygon<f64> {
+    if distance <= 0.0 {
+        return shapes;
+    }
+    let mut buffered = shapes.clone();
+    for polygon in &shapes {
+        for ring in std::iter::once(polygon.exterior()).chain(polygon.interiors()) {
+            for line in ring.lines() {
+                let capsule = rounded_hull(&[line.start, line.end], distance, segments);
+                buffered = buffered.union(&MultiPolygon::new(vec![capsule]));
+            }
+        }
+    }
+    buffered
+}
+
+// Outline of lon / lat points, computed in Web Mercator meters
+pub fn outline(points: &[(f64, f64)], options: &OutlineOptions) -> Outline {
+    if points.is_empty() {
+        return Outline { kind: options.kind, degenerate: true, polygons: Vec::new() };
+    }
+
+    // Unwrap longitudes around the first point so clusters across the antimeridian stay compact
+    let reference = points[0].0;
+    let coords: Vec<Coord<f64>> = points.iter()
+        .map(|(lng, lat)| {
+            let lng = lng + 360.0 * ((reference - lng) / 360.0).round();
+            let (x, y) = to_meters(lng, *lat);
+            Coord { x, y }
+        })
+        .collect();
+    let mean_lat = points.iter().map(|(_, lat)| lat).sum::<f64>() / points.len() as f64;
+    let scale = mercator_scale(mean_lat);
+    let segments = options.segments.max(4);
+    let buffer = options.buffer.max(0.0) * scale;
+    let degenerate_radius = if buffer > 0.0 { buffer } else { options.degenerate_radius.max(0.0) * scale };
+
+    let hull = MultiPoint::from(coords.iter().map(|coord| Point::from(*coord)).collect::<Vec<_>>()).convex_hull();
+    let hull_coords: Vec<Coord<f64>> = hull.exterior().0.clone();
+    let extent = hull_coords.iter()
+        .flat_map(|a| hull_coords.iter().map(move |b| Point::from(*a).euclidean_distance(&Point::from(*b))))
+        .fold(0.0, f64::max);
+
+    // One distinct point or points on a line: a circle or a capsule around the farthest pair
+    let degenerate = extent == 0.0 || hull.unsigned_area() <= 1e-9 * extent * extent;
+    let shapes = if degenerate {
+        MultiPolygon::new(vec![rounded_hull(&hull_coords, degenerate_radius, segments)])
+    } else {
+        match options.kind {
+            OutlineKind::Convex if buffer > 0.0 => MultiPolygon::new(vec![rounded_hull(&hull_coords, buffer, segments)]),
+            OutlineKind::Convex => MultiPolygon::new(vec![hull]),
+            OutlineKind::Concave => {
+                let concave = MultiPoint::from(coords.iter().map(|coord| Point::from(*coord)).collect::<Vec<_>>())
+                    .concave_hull(options.concavity);
+                buffer_polygons(MultiPolygon::new(vec![concave]), buffer, segments)
+            }
+            OutlineKind::Alpha => {
+                let shape = alpha_shape(&coords, options.alpha * scale);
+                let shape = if shape.0.is_empty() { MultiPolygon::new(vec![hull]) } else { shape };
+                buffer_polygons(shape, buffer, segments)
+            }
+        }
+    };
+
+    let to_lng_lat = |ring: &LineString<f64>| -> Vec<[f64; 2]> {
+        ring.coords()
+            .map(|coord| {
+                let (lng, lat) = from_meters(coord.x, coord.y);
+                [lng, lat]
+            })
+            .collect()
+    };
+    let polygons = shapes.iter()
+        .map(|polygon| std::iter::once(polygon.exterior()).chain(polygon.interiors()).map(to_lng_lat).collect())
+        .collect();
+
+    Outline { kind: options.kind, degenerate, polygons }
+}
+
+// Exterior ring of the plain convex hull of lon / lat points, what `get_cluster_info` returned before outlines
+pub fn convex_hull_ring(points: &[(f64, f64)]) -> Vec<Vec<f64>> {
+    let hull = MultiPoint::from(points.iter().map(|(lng, lat)| Point::new(*lng, *lat)).collect::<Vec<_>>()).convex_hull();
+    hull.exterior().coords().map(|coord| vec![coord.x, coord.y]).collect()
+}
+
+impl SuperclusterWrapper {
+    // Outline of a cluster's leaves, cached until the cluster changes or the options do
+    pub fn cluster_outline(&self, cluster_id: usize) -> Result<Rc<Outline>, String> {
+        if let Some(outline) = self.outlines.borrow().get(&cluster_id) {
+            return Ok(outline.clone());
+        }
+
+        let points: Vec<(f64, f64)> = self.leaves_page(cluster_id, usize::MAX, 0)?
+            .iter()
+            .map(|leaf| (leaf.x, leaf.y))
+            .collect();
+        let outline = Rc::new(outline(&points, &self.outline_options));
+        self.outlines.borrow_mut().insert(cluster_id, outline.clone());
+        Ok(outline)
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // e.g. { kind: "concave", concavity: 1.5, buffer: 500 } or { kind: "alpha", alpha: 20000 }
+    #[wasm_bindgen]
+    pub fn set_outline_options(&mut self, options: JsValue) -> Result<(), JsValue> {
+        self.outline_options = if options.is_undefined() || options.is_null() {
+            OutlineOptions::default()
+        } else {
+            from_value(options).map_err(|e| JsValue::from_str(&format!("Invalid outline options: {:?}", e)))?
+        };
+        self.outlines.borrow_mut().clear();
+        Ok(())
+    }
+
+    #[wasm_bindgen]
+    pub fn get_cluster_outline(&self, cluster_id: usize) -> Result<JsValue, JsValue> {
+        match self.cluster_outline(cluster_id) {
+            Ok(outline) => Ok(to_value(outline.as_ref()).unwrap()),
+            Err(e) => Err(JsValue::from_str(&e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outline_kinds_and_degenerate_shapes() {
+        // A 0.1° square with a notch cut into its east side
+        let mut points: Vec<(f64, f64)> = Vec::new();
+        for i in 0..=10 {
+            for j in 0..=10 {
+                if !(i >= 6 && (3..=7).contains(&j)) {
+                    points.push((i as f64 * 0.01, j as f64 * 0.01));
+                }
+            }
+        }
+
+        let convex = outline(&points, &OutlineOptions::default());
+        assert!(!convex.degenerate);
+        assert_eq!(convex.polygons.len(), 1);
+        assert_eq!(convex.polygons[0][0].first(), convex.polygons[0][0].last());
+        let ring = convex_hull_ring(&points);
+        assert_eq!(ring.len(), 5);
+        assert!(convex.polygons[0][0].iter().all(|[lng, lat]| ring.iter().any(|corner| (corner[0] - lng).abs() < 1e-9 && (corner[1] - lat).abs() < 1e-9)));
+
+        // The alpha shape leaves the notch out, the convex hull covers it
+        let alpha = outline(&points, &OutlineOptions { kind: OutlineKind::Alpha, ..Default::default() });
+        let notch = Point::new(0.09, 0.05);
+        let polygon = |rings: &Vec<Vec<[f64; 2]>>| {
+            let ring = |ring: &Vec<[f64; 2]>| LineString::from(ring.iter().map(|[x, y]| (*x, *y)).collect::<Vec<_>>());
+            Polygon::new(ring(&rings[0]), rings[1..].iter().map(ring).collect())
+        };
+        assert!(polygon(&convex.polygons[0]).contains(&notch));
+        assert!(!alpha.polygons.iter().any(|rings| polygon(rings).contains(&notch)));
+        assert!(alpha.polygons.iter().any(|rings| polygon(rings).contains(&Point::new(0.03, 0.05))));
+
+        // One point and points on a line get a circle / capsule of `degenerate_radius` meters
+        let single = outline(&[(10.0, 0.0), (10.0, 0.0)], &OutlineOptions::default());
+        assert!(single.degenerate);
+        assert_eq!(single.polygons[0][0].len(), default_segments() + 1);
+        let line = outline(&[(10.0, 0.0), (10.01, 0.0), (10.02, 0.0)], &OutlineOptions::default());
+        assert!(line.degenerate);
+        let max_lat = line.polygons[0][0].iter().map(|[_, lat]| *lat).fold(f64::MIN, f64::max);
+        assert!((max_lat - 100.0 / 111_320.0).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,310 @@
+// snapshot
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::supercluster::SuperclusterWrapper;
+use crate::supercluster::accumulators::{AccumulatorDeclaration, AccumulatorState};
+use crate::supercluster::filters::ClusterFilter;
+use crate::supercluster::levels::{ClusterLevels, MAX_ZOOM, NO_ITEM};
+use crate::supercluster::outlines::OutlineOptions;
+use crate::supercluster::topology::Topology;
+use crate::supercluster::timeline::TimedStates;
+use crate::supercluster::vector_tiles::TileRelations;
+
+// "SCLW" followed by the format version, little endian, then the JSON body. JSON has no NaN or infinity,
+// so indexes holding them are not written rather than read back with nulls.
+const MAGIC: &[u8; 4] = b"SCLW";
+const VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct SnapshotRef<'a> {
+    levels: &'a ClusterLevels,
+    points: &'a [(f64, f64)],
+    weights: &'a [f64],
+    vertices: &'a [usize],
+    node_ids: &'a [usize],
+    sources: Vec<(usize, usize)>,
+    accumulators: &'a [AccumulatorDeclaration],
+    point_states: &'a [Vec<AccumulatorState>],
+    churn_threshold: f64,
+    churn: usize,
+    max_churn: usize,
+    filter: &'a Option<ClusterFilter>,
+    outline_options: &'a OutlineOptions,
+    weight_property: &'a Option<String>,
+    topology: &'a Option<Topology>,
+    point_timelines: &'a [Vec<TimedStates>],
+}
+
+#[derive(Deserialize)]
+struct Snapshot {
+    levels: ClusterLevels,
+    points: Vec<(f64, f64)>,
+    weights: Vec<f64>,
+    vertices: Vec<usize>,
+    node_ids: Vec<usize>,
+    sources: Vec<(usize, usize)>,
+    accumulators: Vec<AccumulatorDeclaration>,
+    point_states: Vec<Vec<AccumulatorState>>,
+    churn_threshold: f64,
+    churn: usize,
+    max_churn: usize,
+    filter: Option<ClusterFilter>,
+    outline_options: OutlineOptions,
+    weight_property: Option<String>,
+    #[serde(default)]
+    topology: Option<Topology>,
+    #[serde(default)]
+    point_timelines: Vec<Vec<TimedStates>>,
+}
+
+// First field of the snapshot holding NaN or an infinity
+fn non_finite_field(snapshot: &SnapshotRef) -> Option<&'static str> {
+    let outline = snapshot.outline_options;
+    let ranges = snapshot.filter.iter().flat_map(|filter| filter.properties.values());
+    let fields = [
+        ("points", snapshot.points.iter().all(|(lng, lat)| lng.is_finite() && lat.is_finite())),
+        ("weights", snapshot.weights.iter().all(|weight| weight.is_finite())),
+        ("point_states", snapshot.point_states.iter().flatten().all(AccumulatorState::is_finite)),
+        ("point_timelines", snapshot.point_timelines.iter().flatten().all(TimedStates::is_finite)),
+        ("radius", snapshot.levels.radius.is_finite()),
+        ("churn_threshold", snapshot.churn_threshold.is_finite()),
+        ("filter", ranges.flat_map(|range| range.min.into_iter().chain(range.max)).all(f64::is_finite)),
+        ("outline_options", [outline.concavity, outline.alpha, outline.buffer, outline.degenerate_radius].iter().all(|value| value.is_finite())),
+        ("topology", snapshot.topology.as_ref().is_none_or(Topology::is_finite)),
+    ];
+    fields.iter().find(|(_, finite)| !finite).map(|(name, _)| *name)
+}
+
+// One level per zoom from `min_zoom` to `max_zoom + 1`, sources shown as themselves and clusters
+// under ids from `source_capacity` up, so a restored index never indexes out of its arrays
+fn validate_levels(levels: &ClusterLevels) -> Result<(), String> {
+    if levels.min_zoom > levels.max_zoom || levels.max_zoom > MAX_ZOOM || levels.levels.len() != levels.max_zoom - levels.min_zoom + 2 {
+        return Err("Invalid supercluster index: levels do not match the zoom range".to_string());
+    }
+    let top = levels.levels.len() - 1;
+    for (index, level) in levels.levels.iter().enumerate() {
+        if level.zoom != levels.min_zoom + index {
+            return Err(format!("Invalid supercluster index: level {} is at zoom {}", index, level.zoom));
+        }
+        if level.visible.len() > levels.source_capacity {
+            return Err("Invalid supercluster index: more points than the source capacity".to_string());
+        }
+        for (source, id) in level.visible.iter().enumerate() {
+            let point = *id < levels.source_capacity;
+            if *id != NO_ITEM && ((point && *id != source) || (!point && index == top)) {
+                return Err(format!("Invalid supercluster index: item {} of point {} at zoom {}", id, source, level.zoom));
+            }
+        }
+        // Items nest: everything one zoom up lies in exactly one item of this zoom
+        if index < top {
+            let mut parents = HashMap::new();
+            for (id, above) in level.visible.iter().zip(&levels.levels[index + 1].visible) {
+                if (*id == NO_ITEM) != (*above == NO_ITEM) || *parents.entry(*above).or_insert(*id) != *id {
+                    return Err(format!("Invalid supercluster index: items at zoom {} do not nest", level.zoom));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl SuperclusterWrapper {
+    pub fn snapshot(&self) -> Result<Vec<u8>, String> {
+        let mut sources: Vec<(usize, usize)> = self.sources.iter().map(|(vertex, source)| (*vertex, *source)).collect();
+        sources.sort();
+
+        let snapshot = SnapshotRef {
+            levels: &self.levels,
+            points: &self.points,
+            weights: &self.weights,
+            vertices: &self.vertices,
+            node_ids: &self.node_ids,
+            sources,
+            accumulators: &self.accumulators,
+            point_states: &self.point_states,
+            churn_threshold: self.churn_threshold,
+            churn: self.churn,
+            max_churn: self.max_churn,
+            filter: &self.filter,
+            outline_options: &self.outline_options,
+            weight_property: &self.weight_property,
+            topology: &self.topology,
+            point_timelines: &self.point_timelines,
+        };
+        if let Some(field) = non_finite_field(&snapshot) {
+            return Err(format!("Cannot serialize the supercluster index: {} holds NaN or infinite values", field));
+        }
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        // Plain data only, serializing it cannot fail
+        bytes.extend_from_slice(&serde_json::to_vec(&snapshot).unwrap());
+        Ok(bytes)
+    }
+
+    pub fn from_snapshot(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() < 8 || &bytes[..4] != MAGIC {
+            return Err("Not a serialized supercluster index".to_string());
+        }
+        let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        if version != VERSION {
+            return Err(format!("Unsupported supercluster index version {}", version));
+        }
+
+        let snapshot: Snapshot = serde_json::from_slice(&bytes[8..])
+            .map_err(|e| format!("Invalid supercluster index: {}", e))?;
+        let sources = snapshot.points.len();
+        let mut point_timelines = snapshot.point_timelines;
+        // Written before timelines existed
+        if point_timelines.is_empty() {
+            point_timelines.resize_with(sources, Vec::new);
+        }
+        if snapshot.weights.len() != sources
+            || snapshot.vertices.len() != sources
+            || snapshot.node_ids.len() != sources
+            || snapshot.point_states.len() != sources
+            || point_timelines.len() != sources
+        {
+            return Err("Invalid supercluster index: per-point arrays differ in length".to_string());
+        }
+        let accumulators = snapshot.accumulators.len();
+        let timeline_states = point_timelines.iter().flatten().map(|timed| &timed.states);
+        if snapshot.point_states.iter().chain(timeline_states).any(|states| states.len() != accumulators) {
+            return Err("Invalid supercluster index: states do not match the accumulators".to_string());
+        }
+
+        if snapshot.levels.levels.iter().any(|level| level.visible.len() != sources) {
+            return Err("Invalid supercluster index: levels do not match the points".to_string());
+        }
+        validate_levels(&snapshot.levels)?;
+        let vertices = &snapshot.vertices;
+        if snapshot.sources.iter().any(|(vertex, source)| vertices.get(*source) != Some(vertex)) {
+            return Err("Invalid supercluster index: sources do not match the vertices".to_string());
+        }
+        let mut levels = snapshot.levels;
+        levels.restore(&snapshot.points, &snapshot.weights);
+
+        Ok(Self {
+            levels,
+            points: snapshot.points,
+            weights: snapshot.weights,
+            weight_property: snapshot.weight_property,
+            vertices: snapshot.vertices,
+            node_ids: snapshot.node_ids,
+            sources: snapshot.sources.into_iter().collect(),
+            accumulators: snapshot.accumulators,
+            point_states: snapshot.point_states,
+            point_timelines,
+            cluster_states: RefCell::new(HashMap::new()),
+            churn_threshold: snapshot.churn_threshold,
+            churn: snapshot.churn,
+            max_churn: snapshot.max_churn,
+            filter: snapshot.filter,
+            outline_options: snapshot.outline_options,
+            outlines: RefCell::new(HashMap::new()),
+            topology: snapshot.topology,
+            tile_relations: RefCell::new(TileRelations::default()),
+        })
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // The built index with its accumulator states and vertex mapping, to be restored by `from_bytes`
+    #[wasm_bindgen]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, JsValue> {
+        self.snapshot().map_err(|e| JsValue::from_str(&e))
+    }
+
+    // Restore an index written by `to_bytes` without reclustering. Vertex indices refer to
+    // the graph the index was built from.
+    #[wasm_bindgen]
+    pub fn from_bytes(bytes: &[u8]) -> Result<SuperclusterWrapper, JsValue> {
+        Self::from_snapshot(bytes).map_err(|e| JsValue::from_str(&e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supercluster::fixtures::{assert_same_levels, graph_of, index_of, scattered_points};
+    use crate::supercluster::supercluster::ClusterOptions;
+
+    fn snapshot_index() -> SuperclusterWrapper {
+        let graph = graph_of(scattered_points(80, 5).into_iter().enumerate().map(|(id, point)| (point, Some((id % 4) as i32))));
+        index_of(&graph, ClusterOptions::default())
+    }
+
+    fn tampered(index: &SuperclusterWrapper, edit: impl Fn(&mut serde_json::Value)) -> Result<SuperclusterWrapper, String> {
+        let bytes = index.snapshot().unwrap();
+        let mut body: serde_json::Value = serde_json::from_slice(&bytes[8..]).unwrap();
+        edit(&mut body);
+        let mut bytes = bytes[..8].to_vec();
+        bytes.extend_from_slice(&serde_json::to_vec(&body).unwrap());
+        SuperclusterWrapper::from_snapshot(&bytes)
+    }
+
+    #[test]
+    fn snapshot_round_trips() {
+        let index = snapshot_index();
+        let restored = SuperclusterWrapper::from_snapshot(&index.snapshot().unwrap()).unwrap();
+        assert_same_levels(&restored.levels, &index.levels);
+        assert_eq!(restored.sources, index.sources);
+        assert_eq!(restored.points, index.points);
+        assert_eq!(restored.max_churn, index.max_churn);
+
+        for zoom in [0, 4, 17] {
+            let ids = |index: &SuperclusterWrapper| {
+                let mut ids: Vec<usize> = index.clusters_in_bbox(-180.0, -85.0, 180.0, 85.0, zoom).iter().map(|item| item.id).collect();
+                ids.sort_unstable();
+                ids
+            };
+            assert_eq!(ids(&restored), ids(&index));
+        }
+        // Restoring the restored index changes nothing
+        assert_eq!(restored.snapshot(), index.snapshot());
+    }
+
+    #[test]
+    fn broken_snapshots_are_rejected() {
+        let index = snapshot_index();
+        assert!(SuperclusterWrapper::from_snapshot(b"SCLW").is_err());
+        assert!(SuperclusterWrapper::from_snapshot(b"XXXX\x01\x00\x00\x00{}").is_err());
+        assert!(tampered(&index, |_| {}).is_ok());
+
+        type Edit = Box<dyn Fn(&mut serde_json::Value)>;
+        let broken: Vec<Edit> = vec![
+            // A zoom missing
+            Box::new(|body| { body["levels"]["levels"].as_array_mut().unwrap().pop(); }),
+            Box::new(|body| body["levels"]["max_zoom"] = 40.into()),
+            // A point shown as another point, or as a cluster at the points' level
+            Box::new(|body| body["levels"]["levels"][3]["visible"][0] = 1.into()),
+            Box::new(|body| body["levels"]["levels"][17]["visible"][0] = 500.into()),
+            // Cluster ids overlapping the point ids
+            Box::new(|body| body["levels"]["source_capacity"] = 10.into()),
+            Box::new(|body| body["sources"][0][1] = 79.into()),
+            Box::new(|body| { body["weights"].as_array_mut().unwrap().pop(); }),
+            Box::new(|body| { body["point_states"].as_array_mut().unwrap().pop(); }),
+            // A state vector missing its accumulator
+            Box::new(|body| { body["point_states"][0].as_array_mut().unwrap().pop(); }),
+        ];
+        for edit in broken {
+            assert!(tampered(&index, edit).is_err());
+        }
+    }
+
+    #[test]
+    fn non_finite_values_are_not_written() {
+        let mut index = snapshot_index();
+        index.weights[3] = f64::NAN;
+        assert!(index.snapshot().unwrap_err().contains("weights"));
+
+        let mut index = snapshot_index();
+        index.churn_threshold = f64::INFINITY;
+        assert!(index.snapshot().unwrap_err().contains("churn_threshold"));
+    }
+}
@@ -0,0 +1,161 @@
+// spiderfy
+
+use std::f64::consts::PI;
+use wasm_bindgen::prelude::*;
+use serde::Serialize;
+use serde_wasm_bindgen::to_value;
+use crate::supercluster::SuperclusterWrapper;
+use crate::projection::web_mercator::{from_pixel, to_pixel};
+
+// Leaflet.markercluster's layout constants, scaled by `pixel_spacing / CIRCLE_FOOT_SEPARATION`
+const CIRCLE_SPIRAL_SWITCHOVER: usize = 9;
+const CIRCLE_FOOT_SEPARATION: f64 = 25.0;
+const CIRCLE_START_ANGLE: f64 = PI / 6.0;
+const SPIRAL_FOOT_SEPARATION: f64 = 28.0;
+const SPIRAL_LENGTH_START: f64 = 11.0;
+const SPIRAL_LENGTH_FACTOR: f64 = 5.0;
+
+#[derive(Serialize)]
+pub struct SpiderLeaf {
+    pub id: usize,
+    pub vertex_index: usize,
+    pub source_id: usize,
+    // Where the leaf is drawn, lon / lat
+    pub position: [f64; 2],
+    // Screen offset from the cluster in pixels
+    pub offset: [f64; 2],
+}
+
+#[derive(Serialize)]
+pub struct Spider {
+    pub cluster_id: usize,
+    pub center: [f64; 2],
+    pub leaves: Vec<SpiderLeaf>,
+    // Leg lines from the center to every leaf, in `leaves` order
+    pub legs: Vec<[[f64; 2]; 2]>,
+}
+
+// Offsets around the center: evenly on a circle for a few leaves, on a spiral beyond that
+pub fn spider_offsets(count: usize, pixel_spacing: f64) -> Vec<(f64, f64)> {
+    let scale = pixel_spacing / CIRCLE_FOOT_SEPARATION;
+
+    if count < CIRCLE_SPIRAL_SWITCHOVER {
+        let circumference = pixel_spacing * (2.0 + count as f64);
+        let leg_length = circumference / (2.0 * PI);
+        let angle_step = 2.0 * PI / count.max(1) as f64;
+        return (0..count)
+            .map(|i| {
+                let angle = CIRCLE_START_ANGLE + i as f64 * angle_step;
+                (leg_length * angle.cos(), leg_length * angle.sin())
+            })
+            .collect();
+    }
+
+    let separation = SPIRAL_FOOT_SEPARATION * scale;
+    let length_factor = SPIRAL_LENGTH_FACTOR * scale * 2.0 * PI;
+    let mut leg_length = SPIRAL_LENGTH_START * scale;
+    let mut angle: f64 = 0.0;
+    let mut offsets = vec![(0.0, 0.0); count];
+    for i in (0..=count).rev() {
+        if i < count {
+            offsets[i] = (leg_length * angle.cos(), leg_length * angle.sin());
+        }
+        angle += separation / leg_length + i as f64 * 0.0005;
+        leg_length += length_factor / angle;
+    }
+    offsets
+}
+
+impl SuperclusterWrapper {
+    pub fn spider(&self, cluster_id: usize, zoom: f64, pixel_spacing: f64) -> Result<Spider, String> {
+        let item = self.levels.find_item(cluster_id)
+            .ok_or_else(|| format!("No cluster with the specified id {}", cluster_id))?;
+        let (center_lng, center_lat) = item.lng_lat();
+        let (cx, cy) = to_pixel(center_lng, center_lat, zoom);
+
+        let leaves = self.leaves_page(cluster_id, usize::MAX, 0)?;
+        let offsets = spider_offsets(leaves.len(), pixel_spacing.max(1.0));
+
+        let mut spider = Spider {
+            cluster_id,
+            center: [center_lng, center_lat],
+            leaves: Vec::with_capacity(leaves.len()),
+            legs: Vec::with_capacity(leaves.len()),
+        };
+        for (leaf, (dx, dy)) in leaves.into_iter().zip(offsets) {
+            let (lng, lat) = from_pixel(cx + dx, cy + dy, zoom);
+            spider.legs.push([[center_lng, center_lat], [lng, lat]]);
+            spider.leaves.push(SpiderLeaf {
+                id: leaf.id,
+                vertex_index: leaf.vertex_index,
+                source_id: leaf.source_id,
+                position: [lng, lat],
+                offset: [dx, dy],
+            });
+        }
+
+        Ok(spider)
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // Fan the leaves of a cluster out around it so points sharing coordinates can be picked,
+    // `pixel_spacing` is the distance between neighbouring leaves on screen
+    #[wasm_bindgen]
+    pub fn spiderfy(&self, cluster_id: usize, zoom: f64, pixel_spacing: f64) -> Result<JsValue, JsValue> {
+        match self.spider(cluster_id, zoom, pixel_spacing) {
+            Ok(spider) => Ok(to_value(&spider).unwrap()),
+            Err(e) => Err(JsValue::from_str(&e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spider_offsets_circle_then_spiral() {
+        let distance = |a: (f64, f64), b: (f64, f64)| ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt();
+
+        // A few leaves on one circle, neighbours a bit more than the spacing apart
+        let circle = spider_offsets(6, 25.0);
+        let leg_length = 25.0 * 8.0 / (2.0 * PI);
+        assert!(circle.iter().all(|offset| (distance(*offset, (0.0, 0.0)) - leg_length).abs() < 1e-9));
+        for pair in circle.windows(2) {
+            assert!(distance(pair[0], pair[1]) >= 25.0);
+        }
+
+        // Past the switchover the spiral is laid out from the last leaf, legs shrink with the index
+        let spiral = spider_offsets(30, 25.0);
+        assert_eq!(spiral.len(), 30);
+        for pair in spiral.windows(2) {
+            assert!(distance(pair[0], (0.0, 0.0)) > distance(pair[1], (0.0, 0.0)));
+            assert!(distance(pair[0], pair[1]) > 20.0);
+        }
+        assert!(spider_offsets(0, 25.0).is_empty());
+    }
+
+    #[test]
+    fn spider_fans_out_coincident_points() {
+        use crate::supercluster::fixtures::{graph_of, index_of};
+        use crate::supercluster::supercluster::ClusterOptions;
+
+        let graph = graph_of(vec![((10.0, 45.0), None); 12]);
+        let index = index_of(&graph, ClusterOptions::default());
+        let cluster_id = index.levels.level(16).visible[0];
+        assert!(index.levels.is_cluster(cluster_id));
+
+        let spider = index.spider(cluster_id, 16.0, 25.0).unwrap();
+        assert_eq!(spider.leaves.len(), 12);
+        assert_eq!(spider.legs.len(), 12);
+        let (cx, cy) = to_pixel(10.0, 45.0, 16.0);
+        for (leaf, leg) in spider.leaves.iter().zip(&spider.legs) {
+            assert_eq!(leg[1], leaf.position);
+            let (x, y) = to_pixel(leaf.position[0], leaf.position[1], 16.0);
+            assert!((x - cx - leaf.offset[0]).abs() < 1e-6 && (y - cy - leaf.offset[1]).abs() < 1e-6);
+        }
+        assert!(index.spider(1 << 40, 16.0, 25.0).is_err());
+    }
+}
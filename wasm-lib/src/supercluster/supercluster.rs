@@ -1,31 +1,42 @@
 // supercluster
 
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
-use serde::Serialize;
-use serde_wasm_bindgen::to_value;
-use supercluster_rs::{Supercluster, ClusterInfo, ClusterId, SuperclusterBuilder, SuperclusterOptions};
+use serde::{Deserialize, Serialize};
+use serde_wasm_bindgen::{from_value, to_value};
 use crate::graph::suzaku_graph::GraphWrapper;
-use geo::{ConvexHull, CoordsIter};
-use gloo_console::log;
-use geo_types::{MultiPoint, Point, Polygon};
-use js_sys::Array;
-use supercluster_rs::statistics::{Accumulator, Statistic, Statistics, ThresholdCounter};
+use hypergraph::VertexIndex;
+use js_sys::{Array, Float64Array, Int32Array};
+use crate::supercluster::levels::{ClusterLevels, LevelInput, LevelItem, MAX_ZOOM};
+use crate::supercluster::accumulators::{merge_states, statistics, AccumulatorDeclaration, AccumulatorState, StatisticValue};
+use crate::supercluster::filters::ClusterFilter;
+use crate::supercluster::outlines::{convex_hull_ring, Outline, OutlineOptions};
+use crate::supercluster::topology::{Topology, TopologyOptions};
+use crate::projection::viewport::points_bbox;
+use crate::supercluster::timeline::{vertex_timeline, TimedStates};
+use crate::supercluster::vector_tiles::TileRelations;
 
 #[derive(Serialize)]
 pub struct GeometryResult {
     //pub centroid: CenterCoordinates,   /// cluster coordiantes is the center actually
     pub convex_hull: Vec<Vec<f64>>,
+    pub outline: Outline,
     pub children_ids: Vec<usize>,
     pub exp_zoom: Result<usize, String>,
+    pub leaves: Vec<LeafInfo>,
 }
 
 
-
-#[derive(Serialize)]
-pub struct CenterCoordinates {
-    pub center_x: f64,
-    pub center_y: f64,
+// A clustered vertex: its `Node.id`, graph vertex index and supercluster source index
+#[derive(Serialize, Clone)]
+pub struct LeafInfo {
+    pub id: usize,
+    pub vertex_index: usize,
+    pub source_id: usize,
+    pub x: f64,
+    pub y: f64,
 }
 
 
@@ -37,182 +48,395 @@ pub struct ClusterInfoWrapper {
     pub y: f64,
     pub cluster: bool,
     pub point_count: usize,
-    pub statistics: Statistics,
+    // sum of the member weights, `point_count` without a weight property
+    pub total_weight: f64,
+    // one value per accumulator by name, `{ "threshold_counter": { "<thr_id>": count } }` by default
+    pub statistics: HashMap<String, StatisticValue>,
 }
 
 impl ClusterInfoWrapper {
-    pub fn from_external(cluster_info: ClusterInfo) -> Self {
+    pub fn from_item(item: &LevelItem, cluster: bool, statistics: HashMap<String, StatisticValue>) -> Self {
+        let (x, y) = item.lng_lat();
 
         Self {
-            id: cluster_info.id().as_usize(),  // Convert ClusterId to usize
-            x: cluster_info.x(),
-            y: cluster_info.y(),
-            cluster: cluster_info.is_cluster(),
-            point_count: cluster_info.count(),
-            statistics: cluster_info.statistics()
+            id: item.id,
+            x,
+            y,
+            cluster,
+            point_count: item.count(),
+            total_weight: item.weight,
+            statistics,
         }
     }
 }
 
 
+fn default_accumulators() -> Vec<AccumulatorDeclaration> {
+    vec![AccumulatorDeclaration::threshold_counter()]
+}
+
+// Options object accepted by `create_supercluster_with_options`
+#[derive(Deserialize)]
+pub struct ClusterOptions {
+    // Rollups reported in `ClusterInfoWrapper.statistics`, a `threshold_counter` over `thr_id` when omitted
+    #[serde(default = "default_accumulators")]
+    pub accumulators: Vec<AccumulatorDeclaration>,
+    // Fraction of inserted / removed / moved / reweighted points after which the index is rebuilt from scratch
+    #[serde(default = "default_churn_threshold")]
+    pub churn_threshold: f64,
+    // Only vertices matching the filter are clustered, all node vertices when omitted
+    #[serde(default)]
+    pub filter: Option<ClusterFilter>,
+    // Outline drawn by `get_cluster_info`, a convex hull when omitted
+    #[serde(default)]
+    pub outline: OutlineOptions,
+    // Vertex property weighting the cluster centroids and summed into `total_weight`.
+    // Vertices without it weigh 1, negative weights count as 0.
+    #[serde(default)]
+    pub weight_property: Option<String>,
+    // Only merge vertices that are linked or share a community, plain radius clustering when omitted
+    #[serde(default)]
+    pub topology: Option<TopologyOptions>,
+}
+
+fn default_churn_threshold() -> f64 {
+    0.1
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        Self { accumulators: default_accumulators(), churn_threshold: default_churn_threshold(), filter: None, outline: OutlineOptions::default(), weight_property: None, topology: None }
+    }
+}
+
+
 
 
 #[wasm_bindgen]
 pub struct SuperclusterWrapper {
-    supercluster: Supercluster,
-    points: Vec<(f64, f64)>,
+    // every zoom of the index, queried and patched in place
+    pub(crate) levels: ClusterLevels,
+    pub(crate) points: Vec<(f64, f64)>,
+    // weight of every source
+    pub(crate) weights: Vec<f64>,
+    pub(crate) weight_property: Option<String>,
+    // supercluster source index -> graph vertex index (non-node vertices are not clustered)
+    pub(crate) vertices: Vec<usize>,
+    // supercluster source index -> Node.id
+    pub(crate) node_ids: Vec<usize>,
+    // graph vertex index -> supercluster source index
+    pub(crate) sources: HashMap<usize, usize>,
+    pub(crate) accumulators: Vec<AccumulatorDeclaration>,
+    // accumulator states of every source, in `accumulators` order
+    pub(crate) point_states: Vec<Vec<AccumulatorState>>,
+    // states of every source per timeline interval, empty for sources that always exist with `point_states`
+    pub(crate) point_timelines: Vec<Vec<TimedStates>>,
+    // merged accumulator states per cluster id, filled on demand
+    pub(crate) cluster_states: RefCell<HashMap<usize, Rc<Vec<AccumulatorState>>>>,
+    pub(crate) churn_threshold: f64,
+    // points inserted, removed, moved or reweighted since the last build
+    pub(crate) churn: usize,
+    pub(crate) max_churn: usize,
+    pub(crate) filter: Option<ClusterFilter>,
+    pub(crate) outline_options: OutlineOptions,
+    // outlines per cluster id, dropped with `cluster_states`
+    pub(crate) outlines: RefCell<HashMap<usize, Rc<Outline>>>,
+    pub(crate) topology: Option<Topology>,
+    // relations drawn by `get_tile_with_relations`, read from the graph on demand
+    pub(crate) tile_relations: RefCell<TileRelations>,
+}
+
+// Cluster every zoom from 0 to `max_zoom` (at most MAX_ZOOM), leaving room for `max_churn` inserted sources
+pub fn build_levels(input: &LevelInput, max_zoom: usize, radius: f64, max_churn: usize) -> ClusterLevels {
+    let source_capacity = input.points.len() + max_churn + 1;
+    ClusterLevels::build(input, 0, max_zoom.min(MAX_ZOOM), radius, source_capacity)
+}
+
+// Accumulator states of a single vertex
+pub fn vertex_states(accumulators: &[AccumulatorDeclaration], graph: &GraphWrapper, vertex: usize) -> Vec<AccumulatorState> {
+    accumulators.iter()
+        .map(|declaration| AccumulatorState::from_value(declaration, graph.vertex_property(vertex, &declaration.property)))
+        .collect()
+}
+
+pub fn vertex_weight(weight_property: &Option<String>, graph: &GraphWrapper, vertex: usize) -> f64 {
+    match weight_property {
+        Some(property) => graph.vertex_property(vertex, property).unwrap_or(1.0).max(0.0),
+        None => 1.0,
+    }
 }
 
-#[wasm_bindgen]
 impl SuperclusterWrapper {
-    #[wasm_bindgen(constructor)]
-    pub fn new(graph: &GraphWrapper, max_zoom: usize, radius: f64) -> Self {
-        let coords = graph.load_places();
-        let threshold_ids = graph.load_threshold_ids(); // Add this method to load threshold IDs
-
-        //log!(&format!("thresholds_ids {:?}", threshold_ids.clone()));
-        let options = SuperclusterOptions {
-            max_zoom,
-            radius,
-            ..Default::default()
-        };
-        let mut builder = SuperclusterBuilder::new_with_options(coords.len(), options);
-        let coords_clone = coords.clone();
-        for coord in coords {
-            builder.add(coord.0, coord.1);
-        }
+    // Bbox query that handles viewports crossing the antimeridian or spanning the whole world
+    pub fn clusters_in_bbox(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> Vec<&LevelItem> {
+        self.levels.items_in_bbox(min_lng, min_lat, max_lng, max_lat, zoom)
+    }
 
-        // Add accumulators to a HashMap
-        let mut accumulators: HashMap<String, Box<dyn Accumulator>> = HashMap::new();
-        accumulators.insert("threshold_counter".to_string(), Box::new(ThresholdCounter::new()));
+    pub fn is_live(&self, source_id: usize) -> bool {
+        self.vertices.get(source_id)
+            .is_some_and(|vertex| self.sources.get(vertex) == Some(&source_id))
+    }
 
+    pub fn leaf_info(&self, source_id: usize) -> Option<LeafInfo> {
+        if !self.is_live(source_id) {
+            return None;
+        }
+        let (x, y) = *self.points.get(source_id)?;
+        Some(LeafInfo {
+            id: self.node_ids[source_id],
+            vertex_index: self.vertices[source_id],
+            source_id,
+            x,
+            y,
+        })
+    }
 
-        // Pass the accumulators to the finish method
-        let supercluster = builder.finish(accumulators, threshold_ids);
+    // Leaves of a cluster with supercluster's `getLeaves` pagination. A point id returns the point itself.
+    pub fn leaves_page(&self, cluster_id: usize, limit: usize, offset: usize) -> Result<Vec<LeafInfo>, String> {
+        match self.levels.find_item(cluster_id) {
+            Some(item) => Ok(item.members.iter()
+                .filter_map(|source| self.leaf_info(*source))
+                .skip(offset)
+                .take(limit)
+                .collect()),
+            None => Err(format!("No cluster with the specified id {}", cluster_id)),
+        }
+    }
 
-        Self { supercluster, points: coords_clone }
+    // Tight bbox of the live leaves of a cluster or point, see `points_bbox`
+    pub fn leaves_bbox(&self, cluster_id: usize) -> Result<[f64; 4], String> {
+        let item = self.levels.find_item(cluster_id)
+            .ok_or_else(|| format!("No cluster with the specified id {}", cluster_id))?;
+        let points: Vec<(f64, f64)> = item.members.iter()
+            .filter(|source| self.is_live(**source))
+            .map(|source| self.points[*source])
+            .collect();
+        points_bbox(&points).ok_or_else(|| format!("Cluster {} has no leaves", cluster_id))
     }
+}
 
-    #[wasm_bindgen]
-    pub fn get_clusters(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> JsValue {
-        let external_clusters = self.supercluster.get_clusters(min_lng, min_lat, max_lng, max_lat, zoom);
+impl SuperclusterWrapper {
+    pub fn build(graph: &GraphWrapper, max_zoom: usize, radius: f64, cluster_options: ClusterOptions) -> Self {
+        let mut vertices = graph.load_place_vertices();
+        if let Some(filter) = &cluster_options.filter {
+            vertices = filter.select(graph, vertices);
+        }
+        let coords: Vec<(f64, f64)> = vertices.iter()
+            .filter_map(|vertex| graph.graph.get_vertex_weight(VertexIndex(*vertex)).ok())
+            .map(|node| (node.coords.lon, node.coords.lat))
+            .collect();
+        let sources: HashMap<usize, usize> = vertices.iter()
+            .enumerate()
+            .map(|(source, vertex)| (*vertex, source))
+            .collect();
+        let node_ids: Vec<usize> = vertices.iter()
+            .map(|vertex| graph.graph.get_vertex_weight(VertexIndex(*vertex)).map(|node| node.id()).unwrap_or(*vertex))
+            .collect();
+        let max_churn = (cluster_options.churn_threshold.max(0.0) * coords.len() as f64).floor() as usize;
+        let weights: Vec<f64> = vertices.iter()
+            .map(|vertex| vertex_weight(&cluster_options.weight_property, graph, *vertex))
+            .collect();
+        let topology = cluster_options.topology.map(|options| Topology::from_graph(options, graph, &vertices));
+        let input = LevelInput { points: &coords, weights: &weights, vertices: &vertices, topology: topology.as_ref(), live: &|_| true };
+        let levels = build_levels(&input, max_zoom, radius, max_churn);
 
-        let wrappers: Vec<ClusterInfoWrapper> = external_clusters.into_iter()
-            .map(ClusterInfoWrapper::from_external)
+        let accumulators = cluster_options.accumulators;
+        let point_states = vertices.iter()
+            .map(|vertex| vertex_states(&accumulators, graph, *vertex))
+            .collect();
+        let point_timelines = vertices.iter()
+            .map(|vertex| vertex_timeline(&accumulators, graph, *vertex))
             .collect();
 
-        to_value(&wrappers).unwrap()
+        Self {
+            levels,
+            points: coords,
+            weights,
+            weight_property: cluster_options.weight_property,
+            vertices,
+            node_ids,
+            sources,
+            accumulators,
+            point_states,
+            point_timelines,
+            cluster_states: RefCell::new(HashMap::new()),
+            churn_threshold: cluster_options.churn_threshold,
+            churn: 0,
+            max_churn,
+            filter: cluster_options.filter,
+            outline_options: cluster_options.outline,
+            outlines: RefCell::new(HashMap::new()),
+            topology,
+            tile_relations: RefCell::new(TileRelations::default()),
+        }
     }
 
+    // Accumulator states of a cluster, merged from its members and cached
+    pub fn cluster_states(&self, id: usize) -> Rc<Vec<AccumulatorState>> {
+        if !self.levels.is_cluster(id) {
+            return Rc::new(self.point_states.get(id).cloned().unwrap_or_default());
+        }
+        if let Some(states) = self.cluster_states.borrow().get(&id) {
+            return states.clone();
+        }
+
+        let mut states: Vec<AccumulatorState> = self.accumulators.iter().map(AccumulatorState::empty).collect();
+        if let Some(item) = self.levels.find_item(id) {
+            for member in &item.members {
+                merge_states(&mut states, &self.point_states[*member]);
+            }
+        }
 
+        let states = Rc::new(states);
+        self.cluster_states.borrow_mut().insert(id, states.clone());
+        states
+    }
 
-    pub fn get_cluster_expansion_zoom(&self, cluster_id: usize) -> Result<usize, JsValue> {
-        // Convert usize to ClusterId
-        let cl_id = ClusterId::new_source_id(cluster_id); //, zoom, length);
+    pub fn wrap_cluster(&self, item: &LevelItem) -> ClusterInfoWrapper {
+        let states = self.cluster_states(item.id);
+        ClusterInfoWrapper::from_item(item, self.levels.is_cluster(item.id), statistics(&self.accumulators, &states))
+    }
+}
 
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    #[wasm_bindgen(constructor)]
+    pub fn new(graph: &GraphWrapper, max_zoom: usize, radius: f64) -> Self {
+        Self::build(graph, max_zoom, radius, ClusterOptions::default())
+    }
 
-        match self.supercluster.get_cluster_expansion_zoom(    cl_id   ) {
-            Ok(exp_zoom) => Ok(exp_zoom),
-            Err(e) => Err(JsValue::from_str(&e.to_string())),
-        }
-        
+    // `options` declares the accumulators, e.g.
+    // { accumulators: [{ name: "traffic", kind: "sum", property: "traffic" }, { name: "load", kind: "histogram", property: "load", bins: [0.5, 0.8] }] }
+    // or a severity summary of the thresholds, e.g.
+    // { name: "status", kind: "severity", property: "thr_id", severity: [{ thr_id: 0, label: "ok" }, { thr_id: 2, label: "critical" }] }
+    // and optionally the vertices to cluster, e.g. { filter: { thr_ids: [2, 3] } }
+    #[wasm_bindgen]
+    pub fn with_options(graph: &GraphWrapper, max_zoom: usize, radius: f64, options: JsValue) -> Result<SuperclusterWrapper, JsValue> {
+        let options: ClusterOptions = if options.is_undefined() || options.is_null() {
+            ClusterOptions::default()
+        } else {
+            from_value(options).map_err(|e| JsValue::from_str(&format!("Invalid cluster options: {:?}", e)))?
+        };
+
+        Ok(Self::build(graph, max_zoom, radius, options))
     }
 
     #[wasm_bindgen]
-    pub fn get_cluster_info(&self, cluster_id: usize, zoom: usize) -> JsValue {
-        let cl_id = ClusterId::new_source_id(cluster_id);
+    pub fn get_clusters(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize) -> JsValue {
+        let wrappers: Vec<ClusterInfoWrapper> = self.clusters_in_bbox(min_lng, min_lat, max_lng, max_lat, zoom)
+            .into_iter()
+            .map(|item| self.wrap_cluster(item))
+            .collect();
 
-        log!("cl_id", cl_id.as_usize(), self.points.len());
+        to_value(&wrappers).unwrap()
+    }
 
-        match self.supercluster.get_leaves(cl_id, Some(usize::MAX), Some(0)) {
-            Ok(leaves) => {
-                let points: Vec<Point<f64>> = leaves.into_iter()
-                    .map(|info| Point::new(info.x(), info.y()))
-                    .collect();
 
-                let multi_point = MultiPoint::from(points.clone());
 
-                let convex_hull: Polygon<f64> = multi_point.convex_hull();
-                let hull_coords: Vec<Vec<f64>> = convex_hull.exterior().coords_iter()
-                    .map(|coord| vec![coord.x, coord.y])
-                    .collect();
+    pub fn get_cluster_expansion_zoom(&self, cluster_id: usize) -> Result<usize, JsValue> {
+        match self.levels.expansion_zoom(cluster_id) {
+            Some(exp_zoom) => Ok(exp_zoom),
+            None => Err(JsValue::from_str(&format!("No cluster with the specified id {}", cluster_id))),
+        }
+    }
 
-                log!(&format!("Convex Hull Coordinates: {:?}", hull_coords));
+    // Expansion zoom of every id in one call, -1 for unknown ids
+    #[wasm_bindgen]
+    pub fn get_expansion_zooms(&self, cluster_ids: &[u32]) -> Int32Array {
+        let zooms: Vec<i32> = cluster_ids.iter()
+            .map(|id| self.levels.expansion_zoom(*id as usize).map_or(-1, |zoom| zoom as i32))
+            .collect();
+        Int32Array::from(&zooms[..])
+    }
 
+    // [min_lng, min_lat, max_lng, max_lat] of the cluster's leaves, min_lng > max_lng across the antimeridian
+    #[wasm_bindgen]
+    pub fn get_cluster_bbox(&self, cluster_id: usize) -> Result<Float64Array, JsValue> {
+        match self.leaves_bbox(cluster_id) {
+            Ok(bbox) => Ok(Float64Array::from(&bbox[..])),
+            Err(e) => Err(JsValue::from_str(&e)),
+        }
+    }
+
+    // `_zoom` stays for the JS callers, cluster ids are unique across zooms
+    #[wasm_bindgen]
+    pub fn get_cluster_info(&self, cluster_id: usize, _zoom: usize) -> JsValue {
+        match (self.leaves_page(cluster_id, usize::MAX, 0), self.cluster_outline(cluster_id)) {
+            (Ok(leaves), Ok(outline)) => {
+                let points: Vec<(f64, f64)> = leaves.iter().map(|leaf| (leaf.x, leaf.y)).collect();
                 let result = GeometryResult {
-                    children_ids: self.get_children_cluster_ids(cluster_id, zoom),
-                    convex_hull: hull_coords,
+                    convex_hull: convex_hull_ring(&points),
+                    children_ids: self.get_children_cluster_ids(cluster_id, _zoom),
+                    outline: outline.as_ref().clone(),
                     exp_zoom: match self.get_cluster_expansion_zoom(cluster_id) {
                         Ok(zoom) => Ok(zoom),
                         Err(e) => Err(format!("Error getting zoom level: {}", e.as_string().unwrap_or_else(|| "Unknown error".to_string()))),
                     },
+                    leaves,
                 };
 
                 to_value(&result).unwrap()
             }
-            Err(e) => JsValue::from_str(&e.to_string()),
+            (Err(e), _) | (_, Err(e)) => JsValue::from_str(&e),
         }
     }
 
 
-    pub fn get_children_cluster_ids(&self, cluster_id: usize, zoom: usize) -> Vec<usize> {
-        // Convert usize to ClusterId
-        let cl_id = ClusterId::new_source_id(cluster_id);
-
+    // `_zoom` stays for the JS callers like in `get_cluster_info`
+    pub fn get_children_cluster_ids(&self, cluster_id: usize, _zoom: usize) -> Vec<usize> {
         // Vector to collect cluster IDs
         let mut cluster_ids: Vec<usize> = Vec::new();
 
         // Recursive function to collect cluster IDs
-        fn collect_cluster_ids(supercluster: &Supercluster, cl_id: ClusterId, cluster_ids: &mut Vec<usize>) {
-            if let Ok(children) = supercluster.get_children(cl_id) {
-                for info in children {
-                    if info.is_cluster() {
-                        cluster_ids.push(info.id().as_usize());
-                        // Recursively collect children of this cluster
-                        collect_cluster_ids(supercluster, ClusterId::new_source_id(info.id().as_usize()), cluster_ids);
-                    }
+        fn collect_cluster_ids(levels: &ClusterLevels, cluster_id: usize, cluster_ids: &mut Vec<usize>) {
+            for child in levels.children(cluster_id) {
+                if levels.is_cluster(child.id) && child.id != cluster_id {
+                    cluster_ids.push(child.id);
+                    // Recursively collect children of this cluster
+                    collect_cluster_ids(levels, child.id, cluster_ids);
                 }
             }
         }
 
         // Start collecting cluster IDs from the initial cluster
-        collect_cluster_ids(&self.supercluster, cl_id, &mut cluster_ids);
+        collect_cluster_ids(&self.levels, cluster_id, &mut cluster_ids);
 
         cluster_ids
     }
 
+    // Paginated leaves with vertex identities, like supercluster's getLeaves (limit defaults to 10)
     #[wasm_bindgen]
-    pub fn get_custom_leaves(&self, cluster_id: usize) -> JsValue {
-        // Convert usize to ClusterId
-        let cl_id = ClusterId::new_source_id(cluster_id);
-
-        // Vector to collect points
-        let mut points: Vec<Point<f64>> = Vec::new();
-
-        // Recursive function to collect points
-        fn collect_points(wrapper: &SuperclusterWrapper, cl_id: ClusterId, points: &mut Vec<Point<f64>>) {
-            if let Ok(children) = wrapper.supercluster.get_children(cl_id) {
-                for info in children {
-                    if info.is_cluster() {
-                        // Recursively collect children of this cluster
-                        collect_points(wrapper, ClusterId::new_source_id(info.id().as_usize()), points);
-                    } else {
-                        // Collect the point
-                        points.push(Point::new(info.x(), info.y()));
-                    }
-                }
-            }
+    pub fn get_leaves(&self, cluster_id: usize, limit: Option<usize>, offset: Option<usize>) -> Result<JsValue, JsValue> {
+        match self.leaves_page(cluster_id, limit.unwrap_or(10), offset.unwrap_or(0)) {
+            Ok(leaves) => Ok(to_value(&leaves).unwrap()),
+            Err(e) => Err(JsValue::from_str(&e)),
         }
+    }
 
-        // Start collecting points from the initial cluster
-        collect_points(self, cl_id, &mut points);
+    // Supercluster source index of a graph vertex, None for vertices that are not clustered
+    #[wasm_bindgen]
+    pub fn get_source_id(&self, vertex_index: u32) -> Option<usize> {
+        self.sources.get(&(vertex_index as usize)).copied()
+    }
 
-        // Convert Vec<Point<f64>> to js_sys::Array
+    #[wasm_bindgen]
+    pub fn get_vertex_index(&self, source_id: usize) -> Option<u32> {
+        self.vertices.get(source_id).map(|vertex| *vertex as u32)
+    }
+
+    #[wasm_bindgen]
+    pub fn get_custom_leaves(&self, cluster_id: usize) -> JsValue {
+        // Collect the leaves of the cluster
+        let leaves = self.leaves_page(cluster_id, usize::MAX, 0).unwrap_or_default();
+
+        // Convert to js_sys::Array of [x, y, Node.id, vertex index]
         let js_array = Array::new();
-        for point in points {
+        for leaf in leaves {
             let coord_array = Array::new();
-            coord_array.push(&JsValue::from_f64(point.x()));
-            coord_array.push(&JsValue::from_f64(point.y()));
+            coord_array.push(&JsValue::from_f64(leaf.x));
+            coord_array.push(&JsValue::from_f64(leaf.y));
+            coord_array.push(&JsValue::from_f64(leaf.id as f64));
+            coord_array.push(&JsValue::from_f64(leaf.vertex_index as f64));
             js_array.push(&coord_array.into());
         }
 
@@ -222,8 +446,52 @@ impl SuperclusterWrapper {
 
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supercluster::fixtures::{graph_of, index_of};
+
+    #[test]
+    fn leaves_pages_skip_removed_sources() {
+        let graph = graph_of((0..6).map(|id| ((id as f64 * 0.01, 0.0), None)));
+        // No rebuild on the removal, the removed source keeps its id
+        let mut index = index_of(&graph, ClusterOptions { churn_threshold: 1.0, ..Default::default() });
+        assert!(index.remove_vertex(2));
+        assert!(!index.is_live(2));
+
+        let cluster_id = index.levels.level(0).visible[0];
+        let page = |limit: usize, offset: usize| -> Vec<usize> {
+            index.leaves_page(cluster_id, limit, offset).unwrap().iter().map(|leaf| leaf.vertex_index).collect()
+        };
+        assert_eq!(page(2, 0), vec![0, 1]);
+        assert_eq!(page(2, 2), vec![3, 4]);
+        assert_eq!(page(10, 4), vec![5]);
+        assert!(page(10, 5).is_empty());
+
+        // A point is its own single leaf
+        assert_eq!(index.leaves_page(3, 10, 0).unwrap().len(), 1);
+        assert!(index.leaves_page(1 << 40, 10, 0).is_err());
+    }
 
-
-
-
-
+    #[test]
+    fn weights_pull_the_centroid() {
+        let mut graph = graph_of([0.0, 0.4, 1.0, 0.2].map(|lon| ((lon, 0.0), None)));
+        // Vertex 2 has no weight and weighs 1, vertex 3's negative weight counts as 0
+        graph.set_vertex_property(0, "capacity".to_string(), 3.0);
+        graph.set_vertex_property(1, "capacity".to_string(), 1.0);
+        graph.set_vertex_property(3, "capacity".to_string(), -2.0);
+
+        let options = ClusterOptions { weight_property: Some("capacity".to_string()), ..Default::default() };
+        let index = index_of(&graph, options);
+        let cluster = index.wrap_cluster(index.levels.find_item(index.levels.level(0).visible[0]).unwrap());
+        assert_eq!(cluster.point_count, 4);
+        assert_eq!(cluster.total_weight, 5.0);
+        assert!((cluster.x - 1.4 / 5.0).abs() < 1e-6);
+
+        // Unweighted, the plain mean of supercluster
+        let index = index_of(&graph, ClusterOptions::default());
+        let cluster = index.wrap_cluster(index.levels.find_item(index.levels.level(0).visible[0]).unwrap());
+        assert_eq!(cluster.total_weight, 4.0);
+        assert!((cluster.x - 0.4).abs() < 1e-9);
+    }
+}
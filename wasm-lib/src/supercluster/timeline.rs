@@ -0,0 +1,170 @@
+// timeline
+
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_wasm_bindgen::to_value;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::graph::timeline::TimelineInterval;
+use crate::projection::web_mercator::{lat_y, lng_x, x_lng, y_lat};
+use crate::supercluster::SuperclusterWrapper;
+use crate::supercluster::accumulators::{merge_states, statistics, AccumulatorDeclaration, AccumulatorState};
+use crate::supercluster::levels::centroid_weight;
+use crate::supercluster::supercluster::ClusterInfoWrapper;
+
+// Accumulator states of a vertex during one interval of its timeline
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TimedStates {
+    pub interval: TimelineInterval,
+    pub states: Vec<AccumulatorState>,
+}
+
+impl TimedStates {
+    pub fn is_finite(&self) -> bool {
+        let interval = &self.interval;
+        interval.start.is_finite()
+            && interval.end.is_none_or(f64::is_finite)
+            && interval.properties.values().all(|value| value.is_finite())
+            && self.states.iter().all(AccumulatorState::is_finite)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum TimeQuery {
+    At(f64),
+    // Vertices existing at any time in [start, end], with their last status in the window
+    Within(f64, f64),
+}
+
+// States of every interval of a vertex's timeline, empty for vertices without one
+pub fn vertex_timeline(accumulators: &[AccumulatorDeclaration], graph: &GraphWrapper, vertex: usize) -> Vec<TimedStates> {
+    let intervals = match graph.timelines.get(&vertex) {
+        Some(intervals) => intervals,
+        None => return Vec::new(),
+    };
+    intervals.iter()
+        .map(|interval| TimedStates {
+            interval: interval.clone(),
+            states: accumulators.iter()
+                .map(|declaration| AccumulatorState::from_value(declaration, graph.interval_property(vertex, interval, &declaration.property)))
+                .collect(),
+        })
+        .collect()
+}
+
+impl SuperclusterWrapper {
+    // States of a source in effect for the query, None when it does not exist then
+    fn states_at(&self, source: usize, query: TimeQuery) -> Option<&[AccumulatorState]> {
+        let timeline = &self.point_timelines[source];
+        if timeline.is_empty() {
+            return Some(&self.point_states[source]);
+        }
+        // Intervals are sorted by start, the later one wins
+        timeline.iter().rev()
+            .find(|timed| match query {
+                TimeQuery::At(time) => timed.interval.contains(time),
+                TimeQuery::Within(start, end) => timed.interval.overlaps(start, end),
+            })
+            .map(|timed| &timed.states[..])
+    }
+
+    // The index's clusters restricted to the vertices existing at the queried time. Positions, counts,
+    // weights and statistics come from those vertices only, a cluster left with one of them is that point.
+    pub fn clusters_as_of(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize, query: TimeQuery) -> Vec<ClusterInfoWrapper> {
+        let mut clusters = Vec::new();
+        for item in self.clusters_in_bbox(min_lng, min_lat, max_lng, max_lat, zoom) {
+            let active: Vec<(usize, &[AccumulatorState])> = item.members.iter()
+                .filter_map(|source| self.states_at(*source, query).map(|states| (*source, states)))
+                .collect();
+            if active.is_empty() {
+                continue;
+            }
+
+            let mut states: Vec<AccumulatorState> = self.accumulators.iter().map(AccumulatorState::empty).collect();
+            let (mut sum_x, mut sum_y, mut sum_w, mut weight) = (0.0, 0.0, 0.0, 0.0);
+            for (source, source_states) in &active {
+                merge_states(&mut states, source_states);
+                let (lng, lat) = self.points[*source];
+                let w = centroid_weight(self.weights[*source]);
+                sum_x += w * lng_x(lng);
+                sum_y += w * lat_y(lat);
+                sum_w += w;
+                weight += self.weights[*source];
+            }
+
+            let single = active.len() == 1;
+            clusters.push(ClusterInfoWrapper {
+                id: if single { active[0].0 } else { item.id },
+                x: x_lng(sum_x / sum_w),
+                y: y_lat(sum_y / sum_w),
+                cluster: !single && self.levels.is_cluster(item.id),
+                point_count: active.len(),
+                total_weight: weight,
+                statistics: statistics(&self.accumulators, &states),
+            });
+        }
+        clusters
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // `get_clusters` as of `time`, with the status each vertex had then (see `set_vertex_timeline`).
+    // The index is not rebuilt, so scrubbing a timeline only costs the query.
+    #[wasm_bindgen]
+    pub fn get_clusters_at(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize, time: f64) -> JsValue {
+        to_value(&self.clusters_as_of(min_lng, min_lat, max_lng, max_lat, zoom, TimeQuery::At(time))).unwrap()
+    }
+
+    // `get_clusters` over the vertices existing at any time in [start, end], with their last status in the window
+    #[wasm_bindgen]
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_clusters_within(&self, min_lng: f64, min_lat: f64, max_lng: f64, max_lat: f64, zoom: usize, start: f64, end: f64) -> JsValue {
+        to_value(&self.clusters_as_of(min_lng, min_lat, max_lng, max_lat, zoom, TimeQuery::Within(start, end))).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clusters_as_of_keep_the_vertices_existing_then() {
+        use std::collections::HashMap;
+        use crate::supercluster::fixtures::{graph_of, index_of};
+        use crate::supercluster::supercluster::ClusterOptions;
+
+        let mut graph = graph_of((0..3).map(|id| ((10.0 + id as f64 * 0.001, 0.0), Some(1))));
+        let properties = |thr_id: f64| vec![("thr_id".to_string(), thr_id)].into_iter().collect::<HashMap<String, f64>>();
+        graph.timelines.insert(1, vec![TimelineInterval { start: 0.0, end: Some(10.0), properties: properties(2.0) }]);
+        graph.timelines.insert(2, vec![
+            TimelineInterval { start: 0.0, end: Some(20.0), properties: HashMap::new() },
+            TimelineInterval { start: 5.0, end: None, properties: properties(3.0) },
+        ]);
+        let index = index_of(&graph, ClusterOptions::default());
+
+        let at = |time: f64| index.clusters_as_of(-180.0, -85.0, 180.0, 85.0, 0, TimeQuery::At(time));
+        let clusters = at(2.0);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].point_count, 3);
+        assert!(clusters[0].cluster);
+
+        // Vertex 1 is gone at 12, vertex 2 has its later interval in effect
+        let clusters = at(12.0);
+        assert_eq!(clusters[0].point_count, 2);
+        let states = index.states_at(2, TimeQuery::At(12.0)).unwrap();
+        match states {
+            [AccumulatorState::ThresholdCounter(counts)] => assert_eq!(counts.get(&3), Some(&1)),
+            _ => panic!("expected the threshold counter of the second interval"),
+        }
+        assert!(index.states_at(1, TimeQuery::At(12.0)).is_none());
+        assert!(index.states_at(1, TimeQuery::Within(8.0, 30.0)).is_some());
+
+        // A cluster left with one point is that point
+        graph.timelines.insert(0, vec![TimelineInterval { start: 100.0, end: None, properties: HashMap::new() }]);
+        let index = index_of(&graph, ClusterOptions::default());
+        let clusters = index.clusters_as_of(-180.0, -85.0, 180.0, 85.0, 0, TimeQuery::At(50.0));
+        assert_eq!(clusters.len(), 1);
+        assert_eq!((clusters[0].id, clusters[0].cluster, clusters[0].point_count), (2, false, 1));
+        assert!((clusters[0].x - 10.002).abs() < 1e-9);
+    }
+}
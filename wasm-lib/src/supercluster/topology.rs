@@ -0,0 +1,272 @@
+// topology
+
+use std::collections::{HashMap, HashSet};
+use wasm_bindgen::prelude::*;
+use serde::{Deserialize, Serialize};
+use hypergraph::HyperedgeIndex;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::supercluster::SuperclusterWrapper;
+use crate::supercluster::levels::LevelItem;
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TopologyMode {
+    // Members of a cluster form a connected piece of the relation graph
+    Connected,
+    // Members of a cluster share a community
+    Community,
+}
+
+fn default_mode() -> TopologyMode {
+    TopologyMode::Connected
+}
+
+// Constrains radius clustering by the graph, e.g. { mode: "connected", relation_kinds: ["fiber"] }
+// or { mode: "community", property: "community" }
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TopologyOptions {
+    #[serde(default = "default_mode")]
+    pub mode: TopologyMode,
+    // Relations of these kinds link vertices, all relations when omitted
+    #[serde(default)]
+    pub relation_kinds: Option<Vec<String>>,
+    // Vertex property holding the community, the connected components of the relations when omitted.
+    // Vertices without it are never merged in community mode.
+    #[serde(default)]
+    pub property: Option<String>,
+}
+
+// The graph as the clustering sees it, read once per build
+#[derive(Serialize, Deserialize)]
+pub struct Topology {
+    pub options: TopologyOptions,
+    // graph vertex index -> linked clustered vertices, connected mode only
+    links: HashMap<usize, HashSet<usize>>,
+    // graph vertex index -> community, community mode only
+    communities: HashMap<usize, f64>,
+}
+
+fn find(parents: &mut [usize], vertex: usize) -> usize {
+    let mut root = vertex;
+    while parents[root] != root {
+        root = parents[root];
+    }
+    let mut vertex = vertex;
+    while parents[vertex] != root {
+        let next = parents[vertex];
+        parents[vertex] = root;
+        vertex = next;
+    }
+    root
+}
+
+impl Topology {
+    pub fn from_graph(options: TopologyOptions, graph: &GraphWrapper, vertices: &[usize]) -> Self {
+        let clustered: HashSet<usize> = vertices.iter().copied().collect();
+        let mut relations: Vec<Vec<usize>> = Vec::new();
+        for (kind, hyperedge) in graph.relations.values() {
+            if options.relation_kinds.as_ref().is_some_and(|kinds| !kinds.contains(kind)) {
+                continue;
+            }
+            if let Ok(members) = graph.graph.get_hyperedge_vertices(HyperedgeIndex(*hyperedge)) {
+                relations.push(members.iter().map(|vertex| vertex.0).collect());
+            }
+        }
+
+        let mut links: HashMap<usize, HashSet<usize>> = HashMap::new();
+        let mut communities = HashMap::new();
+        match options.mode {
+            TopologyMode::Connected => {
+                for members in &relations {
+                    if let (Some(from), Some(to)) = (members.first(), members.last()) {
+                        if from != to && clustered.contains(from) && clustered.contains(to) {
+                            links.entry(*from).or_default().insert(*to);
+                            links.entry(*to).or_default().insert(*from);
+                        }
+                    }
+                }
+            }
+            TopologyMode::Community => match &options.property {
+                Some(property) => {
+                    for vertex in vertices {
+                        if let Some(community) = graph.vertex_property(*vertex, property) {
+                            communities.insert(*vertex, community);
+                        }
+                    }
+                }
+                None => {
+                    let mut parents: Vec<usize> = (0..graph.graph.count_vertices()).collect();
+                    for members in &relations {
+                        for pair in members.windows(2) {
+                            let (a, b) = (find(&mut parents, pair[0]), find(&mut parents, pair[1]));
+                            parents[a] = b;
+                        }
+                    }
+                    for vertex in vertices {
+                        if *vertex < parents.len() {
+                            communities.insert(*vertex, find(&mut parents, *vertex) as f64);
+                        }
+                    }
+                }
+            },
+        }
+
+        Self { options, links, communities }
+    }
+
+    pub fn is_finite(&self) -> bool {
+        self.communities.values().all(|community| community.is_finite())
+    }
+
+    fn same_community(&self, a: usize, b: usize) -> bool {
+        match (self.communities.get(&a), self.communities.get(&b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    // Which of the `nearby` items a cluster seeded by `seed` takes: in connected mode the ones reached over
+    // links from the growing cluster, in community mode the ones of the seed's community.
+    // `vertices` maps sources to graph vertices, `nearby` is in ascending id order and stays so.
+    pub fn select<'a>(&self, vertices: &[usize], seed: &LevelItem, nearby: &[&'a LevelItem]) -> Vec<&'a LevelItem> {
+        match self.options.mode {
+            TopologyMode::Connected => {
+                let mut owner: HashMap<usize, usize> = HashMap::new();
+                for (index, item) in nearby.iter().enumerate() {
+                    for member in &item.members {
+                        owner.insert(vertices[*member], index);
+                    }
+                }
+
+                let mut selected = vec![false; nearby.len()];
+                let mut frontier: Vec<usize> = seed.members.iter().map(|member| vertices[*member]).collect();
+                while let Some(vertex) = frontier.pop() {
+                    for linked in self.links.get(&vertex).into_iter().flatten() {
+                        if let Some(index) = owner.get(linked) {
+                            if !selected[*index] {
+                                selected[*index] = true;
+                                frontier.extend(nearby[*index].members.iter().map(|member| vertices[*member]));
+                            }
+                        }
+                    }
+                }
+                nearby.iter().zip(selected).filter(|(_, selected)| *selected).map(|(item, _)| *item).collect()
+            }
+            TopologyMode::Community => {
+                let seed_vertex = vertices[seed.members[0]];
+                nearby.iter()
+                    .filter(|item| self.same_community(seed_vertex, vertices[item.members[0]]))
+                    .copied()
+                    .collect()
+            }
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // Re-read the relations (and communities) after they changed and recluster. Vertices inserted
+    // since the index was built have no links or community until then.
+    #[wasm_bindgen]
+    pub fn refresh_topology(&mut self, graph: &GraphWrapper) {
+        if let Some(topology) = self.topology.take() {
+            self.topology = Some(Topology::from_graph(topology.options, graph, &self.vertices));
+            self.rebuild_index();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_match_a_rebuild_under_topology() {
+        use crate::supercluster::fixtures::{assert_same_levels, scattered_points};
+        use crate::supercluster::levels::{ClusterLevels, LevelInput};
+
+        let mut points = scattered_points(120, 3);
+        let weights = vec![1.0; 200];
+        let vertices: Vec<usize> = (0..200).collect();
+        let mut live = vec![true; points.len()];
+
+        // Chains of every third vertex, so close points often may not merge
+        let mut links: HashMap<usize, HashSet<usize>> = HashMap::new();
+        for vertex in 0..197 {
+            links.entry(vertex).or_default().insert(vertex + 3);
+            links.entry(vertex + 3).or_default().insert(vertex);
+        }
+        let communities = (0..200).map(|vertex| (vertex, (vertex % 4) as f64)).collect();
+        for mode in [TopologyMode::Connected, TopologyMode::Community] {
+            let topology = Topology {
+                options: TopologyOptions { mode, relation_kinds: None, property: None },
+                links: links.clone(),
+                communities: HashMap::clone(&communities),
+            };
+            let levels_of = |points: &[(f64, f64)], live: &[bool]| {
+                let is_live = |source: usize| live[source];
+                let input = LevelInput { points, weights: &weights[..points.len()], vertices: &vertices, topology: Some(&topology), live: &is_live };
+                ClusterLevels::build(&input, 0, 14, 60.0, 200)
+            };
+            let mut levels = levels_of(&points, &live);
+
+            for step in 0..60 {
+                let source = match step % 3 {
+                    0 if points.len() < 200 => {
+                        points.push(points[step]);
+                        live.push(true);
+                        points.len() - 1
+                    }
+                    1 => {
+                        let source = (step * 29) % points.len();
+                        live[source] = !live[source];
+                        source
+                    }
+                    _ => {
+                        let source = (step * 17) % points.len();
+                        points[source] = points[(source * 7) % points.len()];
+                        source
+                    }
+                };
+
+                let is_live = |source: usize| live[source];
+                let input = LevelInput { points: &points, weights: &weights[..points.len()], vertices: &vertices, topology: Some(&topology), live: &is_live };
+                levels.update_sources(&input, &[source]);
+                assert_same_levels(&levels, &levels_of(&points, &live));
+            }
+        }
+    }
+
+    #[test]
+    fn topology_reads_links_and_communities() {
+        use crate::supercluster::fixtures::graph_of;
+        use crate::supercluster::levels::{ClusterLevels, LevelInput};
+
+        let mut graph = graph_of((0..5).map(|id| ((id as f64 * 0.001, 0.0), None)));
+        graph.create_relation(10, 0, 1, "fiber".to_string(), 1.0, None).unwrap();
+        graph.create_relation(11, 1, 2, "copper".to_string(), 1.0, None).unwrap();
+        graph.create_relation(12, 3, 4, "fiber".to_string(), 1.0, None).unwrap();
+        let vertices: Vec<usize> = (0..5).collect();
+
+        let fiber = TopologyOptions { mode: TopologyMode::Connected, relation_kinds: Some(vec!["fiber".to_string()]), property: None };
+        let topology = Topology::from_graph(fiber, &graph, &vertices);
+        assert_eq!(topology.links[&0], vec![1].into_iter().collect());
+        assert!(!topology.links.contains_key(&2));
+
+        let components = TopologyOptions { mode: TopologyMode::Community, relation_kinds: None, property: None };
+        let topology = Topology::from_graph(components, &graph, &vertices);
+        assert!(topology.same_community(0, 2));
+        assert!(topology.same_community(3, 4));
+        assert!(!topology.same_community(2, 3));
+
+        // Connected mode takes what the seed reaches over links, through other nearby items
+        let points: Vec<(f64, f64)> = (0..5).map(|vertex| (vertex as f64 * 0.001, 0.0)).collect();
+        let input = LevelInput { points: &points, weights: &[1.0; 5], vertices: &vertices, topology: None, live: &|_| true };
+        let levels = ClusterLevels::build(&input, 0, 2, 40.0, 5);
+        let singles = &levels.level(3).items;
+        let (seed, nearby) = (&singles[&0], vec![&singles[&1], &singles[&2], &singles[&4]]);
+        let all = TopologyOptions { mode: TopologyMode::Connected, relation_kinds: None, property: None };
+        let selected = Topology::from_graph(all, &graph, &vertices).select(&vertices, seed, &nearby);
+        assert_eq!(selected.iter().map(|item| item.id).collect::<Vec<usize>>(), vec![1, 2]);
+    }
+}
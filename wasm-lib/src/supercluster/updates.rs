@@ -0,0 +1,249 @@
+// incremental updates
+
+use wasm_bindgen::prelude::*;
+use hypergraph::VertexIndex;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::supercluster::SuperclusterWrapper;
+use crate::supercluster::accumulators::AccumulatorState;
+use crate::supercluster::levels::LevelInput;
+use crate::supercluster::supercluster::{build_levels, vertex_states, vertex_weight};
+use crate::supercluster::timeline::{vertex_timeline, TimedStates};
+use crate::supercluster::vector_tiles::TileRelations;
+
+impl SuperclusterWrapper {
+    fn invalidate(&self, ids: &[usize]) {
+        let mut cache = self.cluster_states.borrow_mut();
+        let mut outlines = self.outlines.borrow_mut();
+        for id in ids {
+            cache.remove(id);
+            outlines.remove(id);
+        }
+        if !ids.is_empty() {
+            self.tile_relations.borrow_mut().clear_segments();
+        }
+    }
+
+    fn record_churn(&mut self) {
+        self.churn += 1;
+        if self.churn > self.max_churn {
+            self.rebuild_index();
+        }
+    }
+
+    // Recluster the live points from scratch, compacting source ids
+    pub fn rebuild_index(&mut self) {
+        let live: Vec<usize> = (0..self.points.len()).filter(|source| self.is_live(*source)).collect();
+
+        self.points = live.iter().map(|source| self.points[*source]).collect();
+        self.weights = live.iter().map(|source| self.weights[*source]).collect();
+        self.vertices = live.iter().map(|source| self.vertices[*source]).collect();
+        self.node_ids = live.iter().map(|source| self.node_ids[*source]).collect();
+        self.point_states = live.iter().map(|source| self.point_states[*source].clone()).collect();
+        self.point_timelines = live.iter().map(|source| self.point_timelines[*source].clone()).collect();
+        self.sources = self.vertices.iter().enumerate().map(|(source, vertex)| (*vertex, source)).collect();
+
+        self.max_churn = (self.churn_threshold.max(0.0) * self.points.len() as f64).floor() as usize;
+        let input = LevelInput { points: &self.points, weights: &self.weights, vertices: &self.vertices, topology: self.topology.as_ref(), live: &|_| true };
+        self.levels = build_levels(&input, self.levels.max_zoom, self.levels.radius, self.max_churn);
+        self.cluster_states.borrow_mut().clear();
+        self.outlines.borrow_mut().clear();
+        *self.tile_relations.borrow_mut() = TileRelations::default();
+        self.churn = 0;
+    }
+
+    // Recluster around changed sources and drop what was cached for the clusters that changed
+    fn update_sources(&mut self, changed: &[usize]) {
+        let (vertices, sources) = (&self.vertices, &self.sources);
+        let live = |source: usize| sources.get(&vertices[source]) == Some(&source);
+        let input = LevelInput { points: &self.points, weights: &self.weights, vertices, topology: self.topology.as_ref(), live: &live };
+        let updated = self.levels.update_sources(&input, changed);
+        self.invalidate(&updated);
+    }
+
+    fn insert_point(&mut self, vertex: usize, node_id: usize, point: (f64, f64), weight: f64, states: Vec<AccumulatorState>, timeline: Vec<TimedStates>) {
+        let source = self.points.len();
+        self.points.push(point);
+        self.weights.push(weight);
+        self.vertices.push(vertex);
+        self.node_ids.push(node_id);
+        self.point_states.push(states);
+        self.point_timelines.push(timeline);
+        self.sources.insert(vertex, source);
+        // The new source's relations are read with the others on the next tile
+        *self.tile_relations.borrow_mut() = TileRelations::default();
+
+        self.update_sources(&[source]);
+        self.record_churn();
+    }
+
+    fn remove_point(&mut self, vertex: usize) -> bool {
+        let source = match self.sources.get(&vertex) {
+            Some(source) => *source,
+            None => return false,
+        };
+
+        self.sources.remove(&vertex);
+        self.update_sources(&[source]);
+        self.record_churn();
+        true
+    }
+
+    fn move_point(&mut self, vertex: usize, point: (f64, f64)) -> bool {
+        let source = match self.sources.get(&vertex) {
+            Some(source) => *source,
+            None => return false,
+        };
+        if self.points[source] == point {
+            return true;
+        }
+
+        self.points[source] = point;
+        self.update_sources(&[source]);
+        self.record_churn();
+        true
+    }
+
+    // Weight changes move centroids, so they can regroup points like a move does
+    fn update_point_weight(&mut self, vertex: usize, weight: f64) -> bool {
+        let source = match self.sources.get(&vertex) {
+            Some(source) => *source,
+            None => return false,
+        };
+        if self.weights[source] == weight {
+            return true;
+        }
+
+        self.weights[source] = weight;
+        self.update_sources(&[source]);
+        self.record_churn();
+        true
+    }
+
+    // Statistic and timeline changes only touch the clusters containing the point, nothing moves
+    fn update_point_states(&mut self, vertex: usize, states: Vec<AccumulatorState>, timeline: Vec<TimedStates>) -> bool {
+        let source = match self.sources.get(&vertex) {
+            Some(source) => *source,
+            None => return false,
+        };
+
+        self.point_states[source] = states;
+        self.point_timelines[source] = timeline;
+        let chain = self.levels.chain(source);
+        self.invalidate(&chain);
+        true
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // Bring one vertex in line with the graph: inserts, removes (when it is gone, no longer a node or filtered out),
+    // moves and statistic changes are all detected here. Call it for every vertex changed by a refresh.
+    #[wasm_bindgen]
+    pub fn sync_vertex(&mut self, graph: &GraphWrapper, vertex_index: u32) {
+        let vertex = vertex_index as usize;
+        let selected = self.filter.as_ref().is_none_or(|filter| filter.matches(graph, vertex));
+        let node = match graph.graph.get_vertex_weight(VertexIndex(vertex)) {
+            Ok(node) if node.is_node && selected => *node,
+            _ => {
+                self.remove_point(vertex);
+                return;
+            }
+        };
+
+        let point = (node.coords.lon, node.coords.lat);
+        let weight = vertex_weight(&self.weight_property, graph, vertex);
+        let states = vertex_states(&self.accumulators, graph, vertex);
+        let timeline = vertex_timeline(&self.accumulators, graph, vertex);
+
+        if !self.sources.contains_key(&vertex) {
+            self.insert_point(vertex, node.id(), point, weight, states, timeline);
+            return;
+        }
+
+        self.move_point(vertex, point);
+        self.update_point_weight(vertex, weight);
+        self.update_point_states(vertex, states, timeline);
+    }
+
+    #[wasm_bindgen]
+    pub fn insert_vertex(&mut self, graph: &GraphWrapper, vertex_index: u32) -> Result<(), JsValue> {
+        let vertex = vertex_index as usize;
+        if self.filter.as_ref().is_some_and(|filter| !filter.matches(graph, vertex)) {
+            return Err(JsValue::from_str(&format!("Vertex {} does not match the index filter", vertex_index)));
+        }
+        match graph.graph.get_vertex_weight(VertexIndex(vertex)) {
+            Ok(node) if node.is_node => {
+                self.sync_vertex(graph, vertex_index);
+                Ok(())
+            }
+            Ok(_) => Err(JsValue::from_str(&format!("Vertex {} is not a node", vertex_index))),
+            Err(e) => Err(JsValue::from_str(&format!("Error retrieving vertex: {:?}", e))),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn remove_vertex(&mut self, vertex_index: u32) -> bool {
+        self.remove_point(vertex_index as usize)
+    }
+
+    #[wasm_bindgen]
+    pub fn move_vertex(&mut self, vertex_index: u32, lon: f64, lat: f64) -> bool {
+        self.move_point(vertex_index as usize, (lon, lat))
+    }
+
+    // Re-read the accumulated properties, the timeline and the weight of a vertex, e.g. after a new `thr_id`.
+    // With a filter the vertex leaves the index when it no longer matches and joins it when it now does.
+    #[wasm_bindgen]
+    pub fn update_vertex_statistics(&mut self, graph: &GraphWrapper, vertex_index: u32) -> bool {
+        let vertex = vertex_index as usize;
+        if self.filter.is_some() {
+            self.sync_vertex(graph, vertex_index);
+            return self.sources.contains_key(&vertex);
+        }
+        let states = vertex_states(&self.accumulators, graph, vertex);
+        let timeline = vertex_timeline(&self.accumulators, graph, vertex);
+        self.update_point_weight(vertex, vertex_weight(&self.weight_property, graph, vertex));
+        self.update_point_states(vertex, states, timeline)
+    }
+
+    // Points inserted, removed, moved or reweighted since the last full build
+    #[wasm_bindgen]
+    pub fn get_churn(&self) -> usize {
+        self.churn
+    }
+
+    #[wasm_bindgen]
+    pub fn rebuild(&mut self) {
+        self.rebuild_index();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statistics_updates_follow_the_filter() {
+        use crate::graph::suzaku_graph::{Coords, Node};
+        use crate::supercluster::filters::ClusterFilter;
+        use crate::supercluster::fixtures::{graph_of, index_of};
+        use crate::supercluster::supercluster::ClusterOptions;
+
+        let mut graph = graph_of([((0.0, 0.0), Some(1)), ((1.0, 0.0), Some(2)), ((2.0, 0.0), Some(1))]);
+        let filter = ClusterFilter { thr_ids: Some(vec![Some(1)]), ..Default::default() };
+        let options = ClusterOptions { filter: Some(filter), ..Default::default() };
+        let mut index = index_of(&graph, options);
+        assert_eq!(index.get_source_id(1), None);
+
+        // Vertex 0 no longer matches, vertex 1 now does
+        for (vertex, thr_id) in [(0, 2), (1, 1)] {
+            graph.graph.update_vertex_weight(VertexIndex(vertex), Node::new(vertex, Coords { lon: vertex as f64, lat: 0.0 }, Some(thr_id), true)).unwrap();
+        }
+        assert!(!index.update_vertex_statistics(&graph, 0));
+        assert!(index.update_vertex_statistics(&graph, 1));
+        assert!(index.update_vertex_statistics(&graph, 2));
+        assert_eq!(index.get_source_id(0), None);
+        assert!(index.get_source_id(1).is_some());
+        assert_eq!(index.sources.len(), 2);
+    }
+}
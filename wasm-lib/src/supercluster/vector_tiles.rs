@@ -0,0 +1,334 @@
+// vector tiles
+
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use serde_json::Value;
+use crate::graph::suzaku_graph::GraphWrapper;
+use crate::supercluster::SuperclusterWrapper;
+use crate::supercluster::accumulators::{statistics, StatisticValue};
+use crate::supercluster::features::abbreviate;
+use crate::supercluster::levels::{LevelItem, NO_ITEM};
+use crate::supercluster::meta_edges::worse_thr_id;
+use crate::projection::web_mercator::{lat_y, lng_x};
+use crate::mvt::encoder::{encode_tile, LayerBuilder, TagValue};
+
+// MVT has no nested values: bins and counts become one tag per entry, `name_<bin>` / `name_<key>`,
+// severity summaries `name_worst`, `name_dominant`, `name_<thr_id>`, `name_no_threshold` and their labels / percentages
+fn statistic_tags(statistics: HashMap<String, StatisticValue>, tags: &mut Vec<(String, TagValue)>) {
+    for (name, value) in statistics {
+        match value {
+            StatisticValue::Number(Some(value)) => tags.push((name, TagValue::Double(value))),
+            StatisticValue::Number(None) => {}
+            StatisticValue::Count(count) => tags.push((name, TagValue::Uint(count as u64))),
+            StatisticValue::Bins(bins) => {
+                for (bin, count) in bins.into_iter().enumerate() {
+                    tags.push((format!("{}_{}", name, bin), TagValue::Uint(count as u64)));
+                }
+            }
+            StatisticValue::Counts(counts) => {
+                for (key, count) in counts {
+                    tags.push((format!("{}_{}", name, key), TagValue::Uint(count as u64)));
+                }
+            }
+            StatisticValue::Severity(summary) => {
+                if let (Some(worst), Some(label)) = (summary.worst, summary.worst_label) {
+                    tags.push((format!("{}_worst", name), TagValue::Int(worst)));
+                    tags.push((format!("{}_worst_label", name), TagValue::String(label)));
+                }
+                if let (Some(dominant), Some(label)) = (summary.dominant, summary.dominant_label) {
+                    tags.push((format!("{}_dominant", name), TagValue::Int(dominant)));
+                    tags.push((format!("{}_dominant_label", name), TagValue::String(label)));
+                }
+                for share in summary.breakdown {
+                    tags.push((format!("{}_{}", name, share.thr_id), TagValue::Uint(share.count as u64)));
+                    tags.push((format!("{}_{}_percentage", name, share.thr_id), TagValue::Double(share.percentage)));
+                }
+                tags.push((format!("{}_no_threshold", name), TagValue::Uint(summary.no_threshold as u64)));
+                tags.push((format!("{}_no_threshold_percentage", name), TagValue::Double(summary.no_threshold_percentage)));
+            }
+        }
+    }
+}
+
+// Liang-Barsky clipping of a segment to the square [min, max]
+fn clip_segment(a: (f64, f64), b: (f64, f64), min: f64, max: f64) -> Option<((f64, f64), (f64, f64))> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let mut t0: f64 = 0.0;
+    let mut t1: f64 = 1.0;
+
+    for (p, q) in [(-dx, a.0 - min), (dx, max - a.0), (-dy, a.1 - min), (dy, max - a.1)] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+            continue;
+        }
+        let t = q / p;
+        if p < 0.0 {
+            t0 = t0.max(t);
+        } else {
+            t1 = t1.min(t);
+        }
+        if t0 > t1 {
+            return None;
+        }
+    }
+
+    Some(((a.0 + t0 * dx, a.1 + t0 * dy), (a.0 + t1 * dx, a.1 + t1 * dy)))
+}
+
+// A relation of a clustered source, filed under the lower of its two sources
+struct SourceRelation {
+    other: usize,
+    weight: f64,
+    thr_id: Option<i32>,
+}
+
+// Relations aggregated between two items of a zoom, in normalized Web Mercator coordinates.
+// `to` is taken the short way around the world, so x may leave [0, 1].
+struct RelationSegment {
+    source: usize,
+    target: usize,
+    count: usize,
+    weight: f64,
+    worst_thr_id: Option<i32>,
+    from: (f64, f64),
+    to: (f64, f64),
+}
+
+// What the `relations` layer reads: the relations by source, read from the graph once, and their
+// segments per zoom. Segments go when clusters change, the relations when sources are added.
+#[derive(Default)]
+pub struct TileRelations {
+    by_source: Option<Rc<Vec<Vec<SourceRelation>>>>,
+    zooms: HashMap<usize, Rc<Vec<RelationSegment>>>,
+}
+
+impl TileRelations {
+    pub fn clear_segments(&mut self) {
+        self.zooms.clear();
+    }
+}
+
+// Maps normalized Web Mercator coordinates into one tile
+struct TileFrame {
+    z2: f64,
+    x: f64,
+    y: f64,
+    extent: f64,
+}
+
+impl TileFrame {
+    fn project(&self, px: f64, py: f64) -> (f64, f64) {
+        (self.extent * (px * self.z2 - self.x), self.extent * (py * self.z2 - self.y))
+    }
+}
+
+impl SuperclusterWrapper {
+    fn add_point_features(&self, items: Vec<&LevelItem>, frame: &TileFrame, layer: &mut LayerBuilder) {
+        for item in items {
+            let cluster = self.levels.is_cluster(item.id);
+            let mut tags = vec![("cluster".to_string(), TagValue::Bool(cluster))];
+            let states = self.cluster_states(item.id);
+            statistic_tags(statistics(&self.accumulators, &states), &mut tags);
+
+            let ((px, py), id) = if cluster {
+                tags.push(("cluster_id".to_string(), TagValue::Uint(item.id as u64)));
+                tags.push(("point_count".to_string(), TagValue::Uint(item.count() as u64)));
+                let abbreviated = match abbreviate(item.count()) {
+                    Value::String(text) => TagValue::String(text),
+                    _ => TagValue::Uint(item.count() as u64),
+                };
+                tags.push(("point_count_abbreviated".to_string(), abbreviated));
+                (item.xy(), item.id)
+            } else {
+                let (lng, lat) = self.points[item.id];
+                tags.push(("vertex_index".to_string(), TagValue::Uint(self.vertices[item.id] as u64)));
+                ((lng_x(lng), lat_y(lat)), self.node_ids[item.id])
+            };
+
+            let (x, y) = frame.project(px, py);
+            layer.add_point(Some(id as u64), x.round() as i32, y.round() as i32, tags);
+        }
+    }
+
+    fn source_relations(&self, graph: &GraphWrapper) -> Rc<Vec<Vec<SourceRelation>>> {
+        if let Some(by_source) = &self.tile_relations.borrow().by_source {
+            return by_source.clone();
+        }
+
+        let mut by_source: Vec<Vec<SourceRelation>> = (0..self.points.len()).map(|_| Vec::new()).collect();
+        for (from, to, relation) in graph.load_relations() {
+            if let (Some(from), Some(to)) = (self.sources.get(&from), self.sources.get(&to)) {
+                by_source[*from.min(to)].push(SourceRelation { other: *from.max(to), weight: relation.weight, thr_id: relation.thr_id });
+            }
+        }
+
+        let by_source = Rc::new(by_source);
+        self.tile_relations.borrow_mut().by_source = Some(by_source.clone());
+        by_source
+    }
+
+    // Relations between the items of zoom `z` like `cluster_edges` over the whole world, cached per zoom
+    fn relation_segments(&self, graph: &GraphWrapper, z: usize) -> Rc<Vec<RelationSegment>> {
+        if let Some(segments) = self.tile_relations.borrow().zooms.get(&z) {
+            return segments.clone();
+        }
+
+        let by_source = self.source_relations(graph);
+        let level = self.levels.level(z);
+        let severity = self.severity_scale();
+        let mut edges: HashMap<(usize, usize), RelationSegment> = HashMap::new();
+        for (source, relations) in by_source.iter().enumerate() {
+            let from = match level.visible.get(source) {
+                Some(id) if *id != NO_ITEM => *id,
+                _ => continue,
+            };
+            for relation in relations {
+                let to = match level.visible.get(relation.other) {
+                    Some(id) if *id != NO_ITEM && *id != from => *id,
+                    _ => continue,
+                };
+                let key = (from.min(to), from.max(to));
+                let edge = edges.entry(key).or_insert_with(|| {
+                    let from = level.items[&key.0].xy();
+                    let mut to = level.items[&key.1].xy();
+                    if to.0 - from.0 > 0.5 {
+                        to.0 -= 1.0;
+                    } else if from.0 - to.0 > 0.5 {
+                        to.0 += 1.0;
+                    }
+                    RelationSegment { source: key.0, target: key.1, count: 0, weight: 0.0, worst_thr_id: None, from, to }
+                });
+                edge.count += 1;
+                edge.weight += relation.weight;
+                edge.worst_thr_id = worse_thr_id(severity, edge.worst_thr_id, relation.thr_id);
+            }
+        }
+
+        let mut segments: Vec<RelationSegment> = edges.into_values().collect();
+        segments.sort_by_key(|segment| (segment.source, segment.target));
+        let segments = Rc::new(segments);
+        self.tile_relations.borrow_mut().zooms.insert(z, segments.clone());
+        segments
+    }
+
+    // Aggregated relations between the items of zoom `z`, clipped to the buffered tile. Relations
+    // crossing the antimeridian are drawn on both sides of it.
+    fn add_relation_features(&self, graph: &GraphWrapper, z: usize, frame: &TileFrame, buffer: f64, layer: &mut LayerBuilder) {
+        for segment in self.relation_segments(graph, z).iter() {
+            for offset in [-1.0, 0.0, 1.0] {
+                let a = frame.project(segment.from.0 + offset, segment.from.1);
+                let b = frame.project(segment.to.0 + offset, segment.to.1);
+                let (a, b) = match clip_segment(a, b, -buffer, frame.extent + buffer) {
+                    Some(clipped) => clipped,
+                    None => continue,
+                };
+
+                let mut tags = vec![
+                    ("source".to_string(), TagValue::Uint(segment.source as u64)),
+                    ("target".to_string(), TagValue::Uint(segment.target as u64)),
+                    ("count".to_string(), TagValue::Uint(segment.count as u64)),
+                    ("weight".to_string(), TagValue::Double(segment.weight)),
+                ];
+                if let Some(thr_id) = segment.worst_thr_id {
+                    tags.push(("worst_thr_id".to_string(), TagValue::Int(thr_id as i64)));
+                }
+
+                let line = [(a.0.round() as i32, a.1.round() as i32), (b.0.round() as i32, b.1.round() as i32)];
+                layer.add_line(None, &line, tags);
+            }
+        }
+    }
+
+    // Encode tile z/x/y: a `clusters` point layer and, with a graph, a `relations` line layer.
+    // `buffer` is in tile units like `extent`, points within it are repeated across tile edges.
+    pub fn vector_tile(&self, graph: Option<&GraphWrapper>, z: usize, x: u32, y: u32, extent: u32, buffer: u32) -> Vec<u8> {
+        let z2 = 2f64.powi(z as i32);
+        let frame = TileFrame { z2, x: x as f64, y: y as f64, extent: extent as f64 };
+        let p = buffer as f64 / extent.max(1) as f64;
+        let (tx, ty) = (x as f64, y as f64);
+        let top = (ty - p) / z2;
+        let bottom = (ty + 1.0 + p) / z2;
+
+        let mut points = LayerBuilder::new("clusters", extent);
+        self.add_point_features(self.levels.items_in_rect(z, (tx - p) / z2, top, (tx + 1.0 + p) / z2, bottom), &frame, &mut points);
+        // Points across the antimeridian, drawn in the buffer of the first and last column
+        if x == 0 {
+            let wrapped = TileFrame { x: z2, ..frame };
+            self.add_point_features(self.levels.items_in_rect(z, 1.0 - p / z2, top, 1.0, bottom), &wrapped, &mut points);
+        }
+        if tx == z2 - 1.0 {
+            let wrapped = TileFrame { x: -1.0, ..frame };
+            self.add_point_features(self.levels.items_in_rect(z, 0.0, top, p / z2, bottom), &wrapped, &mut points);
+        }
+
+        let mut relations = LayerBuilder::new("relations", extent);
+        if let Some(graph) = graph {
+            self.add_relation_features(graph, z, &frame, buffer as f64, &mut relations);
+        }
+
+        encode_tile(&[points, relations])
+    }
+}
+
+#[wasm_bindgen]
+impl SuperclusterWrapper {
+    // Mapbox Vector Tile bytes with a `clusters` layer, e.g. get_tile(z, x, y, 4096, 64)
+    #[wasm_bindgen]
+    pub fn get_tile(&self, z: usize, x: u32, y: u32, extent: u32, buffer: u32) -> Vec<u8> {
+        self.vector_tile(None, z, x, y, extent, buffer)
+    }
+
+    // Same as `get_tile` plus a `relations` layer of the relations aggregated between clusters.
+    // The relations are read on the first call, `refresh_relations` re-reads them after they change.
+    #[wasm_bindgen]
+    pub fn get_tile_with_relations(&self, graph: &GraphWrapper, z: usize, x: u32, y: u32, extent: u32, buffer: u32) -> Vec<u8> {
+        self.vector_tile(Some(graph), z, x, y, extent, buffer)
+    }
+
+    #[wasm_bindgen]
+    pub fn refresh_relations(&mut self) {
+        *self.tile_relations.borrow_mut() = TileRelations::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_segment_keeps_the_part_inside() {
+        assert_eq!(clip_segment((1.0, 1.0), (3.0, 3.0), 0.0, 4.0), Some(((1.0, 1.0), (3.0, 3.0))));
+        assert_eq!(clip_segment((-2.0, 2.0), (6.0, 2.0), 0.0, 4.0), Some(((0.0, 2.0), (4.0, 2.0))));
+        assert_eq!(clip_segment((-2.0, -2.0), (2.0, 2.0), 0.0, 4.0), Some(((0.0, 0.0), (2.0, 2.0))));
+        assert_eq!(clip_segment((5.0, 0.0), (5.0, 4.0), 0.0, 4.0), None);
+        assert_eq!(clip_segment((-1.0, 3.0), (3.0, 7.0), 0.0, 2.0), None);
+    }
+
+    #[test]
+    fn relations_cross_the_antimeridian() {
+        use crate::supercluster::fixtures::{graph_of, index_of};
+        use crate::supercluster::supercluster::ClusterOptions;
+
+        let mut graph = graph_of([179.9, -179.9, 0.0].map(|lon| ((lon, 0.0), None)));
+        graph.create_relation(10, 0, 1, "fiber".to_string(), 2.0, Some(1)).unwrap();
+        let index = index_of(&graph, ClusterOptions::default());
+
+        // One segment, its far end taken the short way across ±180°
+        let segments = index.relation_segments(&graph, 10);
+        assert_eq!(segments.len(), 1);
+        assert_eq!((segments[0].count, segments[0].weight, segments[0].worst_thr_id), (1, 2.0, Some(1)));
+        assert!((segments[0].to.0 - segments[0].from.0).abs() < 0.01);
+
+        // Drawn in the first and the last column, not across the world
+        let has_relations = |x: u32| {
+            let tile = index.vector_tile(Some(&graph), 10, x, 512, 4096, 64);
+            tile.windows(9).any(|name| name == b"relations")
+        };
+        assert!(has_relations(0));
+        assert!(has_relations(1023));
+        assert!(!has_relations(512));
+    }
+}